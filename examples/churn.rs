@@ -0,0 +1,28 @@
+//! measure allocations that keep reusing the blocks freed last
+//!
+//! every round allocates single units until 10k are live and frees the newest half again, so
+//! the freed blocks sit right behind the ones that stay allocated.
+//!
+//! run with `cargo run --release --example churn [rounds]`
+
+use buddy_allocator::Buddies;
+use std::{env, time::Instant};
+
+fn main() {
+    let mut args = env::args().skip(1).map(|arg| arg.parse().unwrap());
+    let rounds: usize = args.next().unwrap_or(20);
+
+    let buddies = Buddies::new(17, 1, None);
+    let mut live = Vec::with_capacity(10_000);
+
+    let start = Instant::now();
+    for _ in 0..rounds {
+        while live.len() < 10_000 {
+            live.push(buddies.allocate(1, 1).unwrap());
+        }
+        for idx in live.split_off(5_000) {
+            buddies.deallocate(idx, 1);
+        }
+    }
+    println!("{} rounds: {:?}", rounds, start.elapsed());
+}
@@ -0,0 +1,45 @@
+//! measure how often concurrent allocations get in each others way
+//!
+//! run with `cargo run --release --features stats --example contention [threads] [rounds]`
+
+use buddy_allocator::{Buddies, ScanStart};
+use std::{env, sync::Arc, thread, time::Instant};
+
+fn main() {
+    let mut args = env::args().skip(1).map(|arg| arg.parse().unwrap());
+    let threads: usize = args.next().unwrap_or(4);
+    let rounds: usize = args.next().unwrap_or(1000);
+
+    for &scan_start in &[ScanStart::Lowest, ScanStart::Striding] {
+        let buddies = Arc::new(Buddies::new(17, 1, None));
+        buddies.set_scan_start(scan_start);
+
+        let start = Instant::now();
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let buddies = buddies.clone();
+                thread::spawn(move || {
+                    let mut live = Vec::with_capacity(256);
+                    for _ in 0..rounds {
+                        while live.len() < 256 {
+                            live.push(buddies.allocate(1, 1).unwrap());
+                        }
+                        for idx in live.drain(..) {
+                            buddies.deallocate(idx, 1);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        println!(
+            "{:?}: {:?}, {} retries",
+            scan_start,
+            start.elapsed(),
+            buddies.claim_retries()
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! compare the placements with a random mix of allocation sizes and lifetimes
+//!
+//! run with `cargo run --release --example placement [rounds] [seed]`
+
+use buddy_allocator::{Buddies, Placement};
+use std::{env, time::Instant};
+
+/// the size of the block that is probed for after every round
+const BIG: usize = 1 << 10;
+
+fn main() {
+    let mut args = env::args().skip(1).map(|arg| arg.parse().unwrap());
+    let rounds: usize = args.next().unwrap_or(100_000);
+    let seed: usize = args.next().unwrap_or(0x2545_f491);
+
+    for &placement in &[Placement::SmallestBlock, Placement::Clustered] {
+        let buddies = Buddies::new(17, 1, None).with_placement(placement);
+        let mut state = seed;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut live = Vec::new();
+        let mut used = 0;
+        let mut failed = 0;
+        let mut big = 0;
+        let start = Instant::now();
+        for _ in 0..rounds {
+            // mostly small allocations, every now and then a big one
+            let size = 1 << (random() % 64).trailing_zeros().min(10);
+            match buddies.allocate(size, 1) {
+                Some(idx) => {
+                    live.push((idx, size));
+                    used += size;
+                }
+                None => failed += 1,
+            }
+            // keep most of the capacity allocated
+            while used > buddies.capacity() / 16 * 15 {
+                let (idx, size) = live.swap_remove(random() % live.len());
+                buddies.deallocate(idx, size);
+                used -= size;
+            }
+            // check if a big block is still available
+            if let Some(idx) = buddies.allocate(BIG, 1) {
+                buddies.deallocate(idx, BIG);
+                big += 1;
+            }
+        }
+
+        println!(
+            "{:?}: {:?}, {} failed allocations, {} of {} rounds had a free block of {}",
+            placement,
+            start.elapsed(),
+            failed,
+            big,
+            rounds,
+            BIG
+        );
+    }
+}
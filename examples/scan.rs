@@ -0,0 +1,49 @@
+//! measure scans over a big row that is almost full
+//!
+//! order 20 has 1M single unit blocks. all of them are allocated and every round frees 1% of
+//! them at random and allocates them again. their buddies stay allocated, so the freed blocks
+//! can only be found by scanning the row.
+//!
+//! run with `cargo run --release --example scan [align] [rounds]`
+
+use buddy_allocator::Buddies;
+use std::{env, time::Instant};
+
+const BLOCKS: usize = 1 << 20;
+
+fn main() {
+    let mut args = env::args().skip(1).map(|arg| arg.parse().unwrap());
+    let align: usize = args.next().unwrap_or(1);
+    let rounds: usize = args.next().unwrap_or(10);
+
+    let buddies = Buddies::new(21, 1, None);
+    for _ in 0..BLOCKS {
+        buddies.allocate(1, 1).unwrap();
+    }
+
+    // only even blocks are freed, so the blocks can't merge with their buddies
+    let mut state = 0x2545_f491_u32;
+    let mut picked = vec![false; BLOCKS / 2];
+    let mut freed = Vec::with_capacity(BLOCKS / 100);
+    while freed.len() < BLOCKS / 100 {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let pair = state as usize % (BLOCKS / 2);
+        if !picked[pair] {
+            picked[pair] = true;
+            freed.push(pair * 2);
+        }
+    }
+
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for &idx in &freed {
+            buddies.deallocate(idx, 1);
+        }
+        for _ in 0..freed.len() {
+            buddies.allocate(1, align).unwrap();
+        }
+    }
+    println!("align {}, {} rounds: {:?}", align, rounds, start.elapsed());
+}
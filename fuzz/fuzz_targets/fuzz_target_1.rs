@@ -2,9 +2,9 @@
 #![feature(wrapping_next_power_of_two)]
 use libfuzzer_sys::fuzz_target;
 
-use alloc_wg::alloc::ReallocPlacement;
+use alloc_wg::alloc::{Global, ReallocPlacement};
 use arbitrary::Arbitrary;
-use buddy_allocator::Buddies;
+use buddy_allocator::{unsync::Cells, Atomics, Backend, GenericBuddies};
 use env_logger::{try_init_from_env, Env};
 use log::trace;
 use std::{
@@ -22,6 +22,8 @@ enum Size {
 struct Actions {
     size: Size,
     multiplier: usize,
+    /// run against the single threaded backend
+    unsync: bool,
     actions: Vec<Action>,
 }
 
@@ -88,7 +90,7 @@ impl Actions {
                         return Err(());
                     }
                 }
-                Action::Grow { index, size } => {
+                Action::Grow { index, size, .. } => {
                     if allocated == 0 {
                         return Err(());
                     }
@@ -100,7 +102,7 @@ impl Actions {
                     *size = (*size).max(ids[index]);
                     ids.insert(*index, *size);
                 }
-                Action::Shrink { index, size } => {
+                Action::Shrink { index, size, .. } => {
                     if allocated == 0 {
                         return Err(());
                     }
@@ -109,7 +111,11 @@ impl Actions {
                         return Err(());
                     }
                     *size %= max_size;
-                    *size = (*size).min(ids[index]);
+                    *size = (*size).min(ids[index]).max(1);
+                    if *size > ids[index] {
+                        // shrinking to size 0 isn't allowed
+                        return Err(());
+                    }
                     ids.insert(*index, *size);
                 }
             }
@@ -124,126 +130,186 @@ enum Action {
     Allocate { size: usize, align: usize },
     AllocateAt {size: usize, idx: usize},
     Deallocate { index: usize },
-    Grow { index: usize, size: usize },
-    Shrink { index: usize, size: usize },
+    Grow { index: usize, size: usize, in_place: bool },
+    Shrink { index: usize, size: usize, may_move: bool },
 }
 
 fuzz_target!(|actions: Actions| {
     try_init_from_env(Env::new()).ok();
 
-    let res: Result<(), ()> = (move || {
-        let mut actions = actions;
-        actions.sanitize()?;
+    let mut actions = actions;
+    if actions.sanitize().is_err() {
+        return;
+    }
+    let res = if actions.unsync {
+        run::<Cells>(actions)
+    } else {
+        run::<Atomics>(actions)
+    };
+    res.ok();
+});
 
-        let mut allocated = 0;
-        let mut references = HashMap::new();
-
-        trace!(
-            "Creating buddy size={:?}, multiplier={}",
-            actions.size,
-            actions.multiplier,
-        );
-        let buddies;
-        match actions.size {
-            Size::ByOrder(max_order) => {
-                buddies = Buddies::new(max_order, actions.multiplier, None);
-            }
-            Size::ByCapacity(capacity) => {
-                buddies = Buddies::with_capacity(capacity, actions.multiplier);
-            }
+fn run<B: Backend>(actions: Actions) -> Result<(), ()> {
+    let mut allocated = 0;
+    let mut references = HashMap::new();
+
+    trace!(
+        "Creating buddy size={:?}, multiplier={}",
+        actions.size,
+        actions.multiplier,
+    );
+    let buddies;
+    match actions.size {
+        Size::ByOrder(max_order) => {
+            buddies = GenericBuddies::<Global, B>::new(max_order, actions.multiplier, None);
+        }
+        Size::ByCapacity(capacity) => {
+            buddies = GenericBuddies::<Global, B>::with_capacity(capacity, actions.multiplier);
         }
-        let mut fake_memory = vec![false; buddies.capacity()];
+    }
+    let mut fake_memory = vec![false; buddies.capacity()];
 
-        for action in actions.actions {
-            match action {
-                Action::Allocate { size, align } => {
-                    trace!("Allocating with size {}, alignment {}", size, align);
+    for action in actions.actions {
+        match action {
+            Action::Allocate { size, align } => {
+                trace!("Allocating with size {}, alignment {}", size, align);
+
+                let id = allocated;
+                allocated += 1;
 
+                let idx = buddies.allocate(size, align).ok_or(())?;
+                trace!("Allocated at {} with size {}", idx, size);
+                assert_eq!(idx & (align - 1), 0, "alignment is off");
+                for i in idx..idx + size {
+                    assert!(!fake_memory[i]);
+                    fake_memory[i] = true;
+                }
+
+                references.insert(id, (idx, size));
+            }
+            Action::AllocateAt {size, idx} => {
+                trace!("Allocating at {} with size {}",idx, size);
+                if buddies.allocate_at(size, idx) {
+                    trace!("Allocated at {} with size {}", idx, size);
                     let id = allocated;
                     allocated += 1;
 
-                    let idx = buddies.allocate(size, align).ok_or(())?;
-                    trace!("Allocated at {} with size {}", idx, size);
-                    assert_eq!(idx & (align - 1), 0, "alignment is off");
                     for i in idx..idx + size {
                         assert!(!fake_memory[i]);
                         fake_memory[i] = true;
                     }
-
                     references.insert(id, (idx, size));
+                } else {
+                    trace!("Failed allocation");
+                    return Err(())
                 }
-                Action::AllocateAt {size, idx} => {
-                    trace!("Allocating at {} with size {}",idx, size);
-                    if buddies.allocate_at(size, idx) {
-                        trace!("Allocated at {} with size {}", idx, size);
-                        let id = allocated;
-                        allocated += 1;
-
-                        for i in idx..idx + size {
-                            assert!(!fake_memory[i]);
-                            fake_memory[i] = true;
-                        }
-                        references.insert(id, (idx, size));
-                    } else {
-                        trace!("Failed allocation");
-                        return Err(())
-                    }
+            }
+            Action::Deallocate { index } => {
+                let (idx, size) = references.remove(&index).unwrap();
+                trace!("Deallocating {} with size {}", idx, size);
+                for i in idx..idx + size {
+                    assert!(fake_memory[i]);
+                    fake_memory[i] = false;
                 }
-                Action::Deallocate { index } => {
-                    let (idx, size) = references.remove(&index).unwrap();
-                    trace!("Deallocating {} with size {}", idx, size);
-                    for i in idx..idx + size {
-                        assert!(fake_memory[i]);
-                        fake_memory[i] = false;
-                    }
-                    buddies.deallocate(idx, size);
-                }
-                Action::Grow {
-                    index,
-                    size: new_size,
-                } => {
-                    let (idx, size) = references.get_mut(&index).unwrap();
-                    trace!("Growing {} with size {} to {}", idx, size, new_size);
-                    let old_idx = *idx;
-
-                    *idx = buddies
-                        .grow(*idx, *size, new_size as usize, ReallocPlacement::MayMove)
-                        .ok_or(())?;
-                    if *idx != old_idx {
-                        trace!("Location changed from {} to {}", old_idx, *idx);
-                    }
-
-                    let min = (*idx).min(old_idx);
-                    let max = (*idx + new_size).max(old_idx + *size);
+                buddies.deallocate(idx, size);
+            }
+            Action::Grow {
+                index,
+                size: new_size,
+                in_place,
+            } => {
+                let (idx, size) = references.get_mut(&index).unwrap();
+                trace!(
+                    "Growing {} with size {} to {} (in place: {})",
+                    idx,
+                    size,
+                    new_size,
+                    in_place
+                );
+                let old_idx = *idx;
+                let placement = if in_place {
+                    ReallocPlacement::InPlace
+                } else {
+                    ReallocPlacement::MayMove
+                };
 
-                    for i in min..max {
-                        let was_inside = i >= old_idx && i < old_idx + *size;
-                        let is_inside = i >= *idx && i < *idx + new_size;
-                        assert_eq!(fake_memory[i], was_inside, "at {}", i);
-                        fake_memory[i] = is_inside;
+                match buddies.grow(*idx, *size, new_size as usize, placement) {
+                    Some(new_idx) => *idx = new_idx,
+                    None => {
+                        trace!("Failed growing");
+                        // a failed grow leaves the allocation untouched
+                        assert_eq!(
+                            buddies.allocation_size_at(old_idx),
+                            Some(buddies.real_size_for_allocation(*size))
+                        );
+                        return Err(());
                     }
+                }
+                if in_place {
+                    assert_eq!(*idx, old_idx, "in place grow moved the allocation");
+                }
+                if *idx != old_idx {
+                    trace!("Location changed from {} to {}", old_idx, *idx);
+                }
 
-                    *size = new_size;
+                // the allocation either grew over its buddies or moved to another block
+                for i in old_idx..old_idx + *size {
+                    assert!(fake_memory[i], "{} wasn't allocated", i);
+                    fake_memory[i] = false;
+                }
+                for i in *idx..*idx + new_size {
+                    assert!(!fake_memory[i], "{} was already allocated", i);
+                    fake_memory[i] = true;
                 }
-                Action::Shrink {
-                    index,
-                    size: new_size,
-                } => {
-                    let (idx, size) = references.get_mut(&index).unwrap();
-                    trace!("Shrinking size {} at {} to {}", size, idx, new_size);
 
-                    for i in *idx + new_size..*idx + *size {
-                        assert!(fake_memory[i], "{} wasn't allocated", i);
-                        fake_memory[i] = false;
-                    }
+                *size = new_size;
+            }
+            Action::Shrink {
+                index,
+                size: new_size,
+                may_move,
+            } => {
+                let (idx, size) = references.get_mut(&index).unwrap();
+                trace!(
+                    "Shrinking size {} at {} to {} (may move: {})",
+                    size,
+                    idx,
+                    new_size,
+                    may_move
+                );
+                let old_idx = *idx;
+                let placement = if may_move {
+                    ReallocPlacement::MayMove
+                } else {
+                    ReallocPlacement::InPlace
+                };
 
-                    buddies.shrink(*idx, *size, new_size as usize);
-                    *size = new_size;
+                *idx = buddies.shrink_with_placement(*idx, *size, new_size, 1, placement);
+                if !may_move {
+                    assert_eq!(*idx, old_idx, "in place shrink moved the allocation");
                 }
+                if *idx != old_idx {
+                    trace!("Location changed from {} to {}", old_idx, *idx);
+                }
+
+                // the allocation either shrunk or moved to another block
+                for i in old_idx..old_idx + *size {
+                    assert!(fake_memory[i], "{} wasn't allocated", i);
+                    fake_memory[i] = false;
+                }
+                for i in *idx..*idx + new_size {
+                    assert!(!fake_memory[i], "{} was already allocated", i);
+                    fake_memory[i] = true;
+                }
+
+                *size = new_size;
             }
         }
 
-        Ok(())
-    })();
-    res.ok();
-});
+        if let Err(err) = buddies.verify_integrity() {
+            panic!("{}", err);
+        }
+    }
+
+    Ok(())
+}
@@ -1,15 +1,55 @@
-use crate::Buddies;
+#[cfg(feature = "allocator-api")]
+mod allocator_api;
+
+use crate::{Buddies, TryNewError};
 use alloc_wg::alloc::{AllocErr, AllocInit, AllocRef, Layout, MemoryBlock, ReallocPlacement};
 use core::{
     convert::TryInto,
-    ptr::{write_bytes, NonNull},
+    fmt,
+    mem::ManuallyDrop,
+    ptr::{self, copy, copy_nonoverlapping, write_bytes, NonNull},
 };
 
+/// error returned when creating a [BuddyAllocator](BuddyAllocator)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuddyAllocatorError {
+    /// creating the buddies failed
+    Metadata(TryNewError),
+    /// allocating the memory handed out by the allocator failed
+    Memory,
+    /// the parameters are invalid
+    InvalidParameters(&'static str),
+}
+
+impl fmt::Display for BuddyAllocatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BuddyAllocatorError::Metadata(err) => {
+                write!(f, "failed to create the buddies: {}", err)
+            }
+            BuddyAllocatorError::Memory => write!(f, "failed to allocate the memory"),
+            BuddyAllocatorError::InvalidParameters(msg) => {
+                write!(f, "invalid parameters: {}", msg)
+            }
+        }
+    }
+}
+
+impl From<TryNewError> for BuddyAllocatorError {
+    fn from(err: TryNewError) -> Self {
+        BuddyAllocatorError::Metadata(err)
+    }
+}
+
 pub struct BuddyAllocator<AR: AllocRef> {
     allocator: AR,
     memory: MemoryBlock,
     layout: Layout,
     buddies: Buddies<AR>,
+    /// the alignment of the memory, allocations can't be aligned any further
+    align: usize,
+    /// whether the memory was allocated with `allocator` and has to be released on drop
+    owns_memory: bool,
 }
 
 unsafe impl<AR: AllocRef + Send> Send for BuddyAllocator<AR> {}
@@ -18,38 +58,74 @@ unsafe impl<AR: AllocRef + Sync> Sync for BuddyAllocator<AR> {}
 impl<AR: AllocRef + Copy> BuddyAllocator<AR> {
     /// try to create a new buddy allocator
     ///
-    /// see [Buddies::new]
+    /// see [Buddies::try_new_in]
     /// ```
     /// #![feature(allocator_api)]
-    /// use alloc_wg::alloc::Global;
+    /// use alloc_wg::alloc::{AllocErr, AllocInit, AllocRef, Global, Layout, MemoryBlock};
     /// use alloc_wg::boxed::Box;
-    /// use buddy_allocator::BuddyAllocator;
+    /// use buddy_allocator::{BuddyAllocator, BuddyAllocatorError, TryNewError};
+    /// use core::ptr::NonNull;
     ///
     /// let allocator = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
     /// let boxed = Box::new_in(123, &allocator);
+    ///
+    /// /// fails allocations bigger than the limit
+    /// #[derive(Clone, Copy)]
+    /// struct Limited(usize);
+    ///
+    /// unsafe impl AllocRef for Limited {
+    ///     fn alloc(&mut self, layout: Layout, init: AllocInit) -> Result<MemoryBlock, AllocErr> {
+    ///         if layout.size() > self.0 {
+    ///             return Err(AllocErr);
+    ///         }
+    ///         Global.alloc(layout, init)
+    ///     }
+    ///
+    ///     unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+    ///         Global.dealloc(ptr, layout)
+    ///     }
+    /// }
+    ///
+    /// assert!(matches!(
+    ///     BuddyAllocator::try_new(5, 16, None, Limited(0)),
+    ///     Err(BuddyAllocatorError::Metadata(TryNewError::AllocError))
+    /// ));
+    /// assert!(matches!(
+    ///     BuddyAllocator::try_new(5, 16, None, Limited(128)),
+    ///     Err(BuddyAllocatorError::Memory)
+    /// ));
+    /// assert!(BuddyAllocator::try_new(5, 16, None, Limited(256)).is_ok());
+    /// assert!(matches!(
+    ///     BuddyAllocator::try_new(5, 15, None, Global),
+    ///     Err(BuddyAllocatorError::Metadata(TryNewError::InvalidParameters(_)))
+    /// ));
     /// ```
     pub fn try_new(
         max_order: usize,
         multiplier: usize,
         max_idx: Option<usize>,
         mut allocator: AR,
-    ) -> Result<Self, AllocErr> {
-        let buddies = Buddies::new_in(max_order, multiplier, max_idx, allocator);
+    ) -> Result<Self, BuddyAllocatorError> {
+        let buddies = Buddies::try_new_in(max_order, multiplier, max_idx, allocator)?;
         let layout = Layout::from_size_align(buddies.capacity(), buddies.capacity())
-            .map_err(|_| AllocErr)?;
+            .map_err(|_| BuddyAllocatorError::Memory)?;
 
-        let memory = allocator.alloc(layout, AllocInit::Uninitialized)?;
+        let memory = allocator
+            .alloc(layout, AllocInit::Uninitialized)
+            .map_err(|_| BuddyAllocatorError::Memory)?;
         Ok(BuddyAllocator {
             allocator,
             memory,
             layout,
             buddies,
+            align: layout.align(),
+            owns_memory: true,
         })
     }
 
     /// try to create a new buddy allocator
     ///
-    /// see [Buddies::with_capacity]
+    /// see [Buddies::try_with_capacity_in]
     /// ```
     /// #![feature(allocator_api)]
     /// use alloc_wg::alloc::Global;
@@ -63,21 +139,170 @@ impl<AR: AllocRef + Copy> BuddyAllocator<AR> {
         capacity: usize,
         multiplier: usize,
         mut allocator: AR,
-    ) -> Result<Self, AllocErr> {
-        let buddies = Buddies::with_capacity_in(capacity, multiplier, allocator);
+    ) -> Result<Self, BuddyAllocatorError> {
+        let buddies = Buddies::try_with_capacity_in(capacity, multiplier, allocator)?;
         let layout =
             Layout::from_size_align(buddies.capacity(), buddies.capacity().next_power_of_two())
-                .map_err(|_| AllocErr)?;
+                .map_err(|_| BuddyAllocatorError::Memory)?;
 
-        let memory = allocator.alloc(layout, AllocInit::Uninitialized)?;
+        let memory = allocator
+            .alloc(layout, AllocInit::Uninitialized)
+            .map_err(|_| BuddyAllocatorError::Memory)?;
         Ok(BuddyAllocator {
             allocator,
             memory,
             layout,
             buddies,
+            align: layout.align(),
+            owns_memory: true,
+        })
+    }
+
+    /// create a buddy allocator managing memory that was provided by someone else
+    ///
+    /// the capacity is `size` rounded down to a multiple of `multiplier`, see
+    /// [Buddies::try_with_capacity_in]. only the buddies are allocated with `allocator`, the
+    /// memory isn't released on drop.
+    ///
+    /// # Safety
+    /// the memory at `ptr` must be valid for reads and writes of `size` bytes and must not be
+    /// used by anything else while the allocator or one of its allocations is alive.
+    ///
+    /// the allocations are only aligned relative to `ptr`, so `ptr` must be aligned at least to
+    /// `multiplier`. allocations that need a bigger alignment than `ptr` has fail.
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocInit, AllocRef, Global, Layout};
+    /// use buddy_allocator::{BuddyAllocator, BuddyAllocatorError, TryNewError};
+    /// use core::ptr::NonNull;
+    ///
+    /// #[repr(align(64))]
+    /// struct Region([u8; 1000]);
+    ///
+    /// let region = Box::leak(Box::new(Region([1; 1000])));
+    /// let ptr = NonNull::from(&mut region.0).cast::<u8>();
+    /// let allocator = unsafe { BuddyAllocator::from_raw_parts(ptr, 1000, 16, Global) }.unwrap();
+    /// assert_eq!(allocator.capacitiy(), 992);
+    ///
+    /// let layout = Layout::from_size_align(512, 64).unwrap();
+    /// let memory = (&allocator).alloc(layout, AllocInit::Zeroed).unwrap();
+    /// assert_eq!(memory.ptr, ptr);
+    ///
+    /// // the region is still there after the allocator is gone
+    /// drop(allocator);
+    /// assert_eq!(region.0[0], 0);
+    /// assert_eq!(region.0[512], 1);
+    ///
+    /// assert!(matches!(
+    ///     unsafe { BuddyAllocator::from_raw_parts(ptr, 8, 16, Global) },
+    ///     Err(BuddyAllocatorError::Metadata(TryNewError::InvalidParameters(_)))
+    /// ));
+    /// let unaligned = unsafe { NonNull::new_unchecked(ptr.as_ptr().add(8)) };
+    /// assert!(matches!(
+    ///     unsafe { BuddyAllocator::from_raw_parts(unaligned, 512, 16, Global) },
+    ///     Err(BuddyAllocatorError::InvalidParameters(_))
+    /// ));
+    ///
+    /// // a ptr that is aligned to 64, but not to 128
+    /// let offset = if ptr.as_ptr() as usize % 128 == 0 { 64 } else { 0 };
+    /// let ptr = unsafe { NonNull::new_unchecked(ptr.as_ptr().add(offset)) };
+    /// let allocator = unsafe { BuddyAllocator::from_raw_parts(ptr, 512, 16, Global) }.unwrap();
+    /// let layout = Layout::from_size_align(16, 128).unwrap();
+    /// assert!((&allocator).alloc(layout, AllocInit::Zeroed).is_err());
+    /// let layout = Layout::from_size_align(16, 64).unwrap();
+    /// assert!((&allocator).alloc(layout, AllocInit::Zeroed).is_ok());
+    /// ```
+    pub unsafe fn from_raw_parts(
+        ptr: NonNull<u8>,
+        size: usize,
+        multiplier: usize,
+        allocator: AR,
+    ) -> Result<Self, BuddyAllocatorError> {
+        let buddies = Buddies::try_with_capacity_in(
+            size & !multiplier.wrapping_sub(1),
+            multiplier,
+            allocator,
+        )?;
+        let align = 1 << (ptr.as_ptr() as usize).trailing_zeros();
+        if align < multiplier {
+            return Err(BuddyAllocatorError::InvalidParameters(
+                "ptr is not aligned to the multiplier",
+            ));
+        }
+        let layout = Layout::from_size_align(buddies.capacity(), multiplier)
+            .map_err(|_| BuddyAllocatorError::Memory)?;
+
+        Ok(BuddyAllocator {
+            allocator,
+            memory: MemoryBlock { ptr, size },
+            layout,
+            buddies,
+            align,
+            owns_memory: false,
         })
     }
 
+    /// take the allocator apart without releasing the memory
+    ///
+    /// returns the memory, its layout and the buddies keeping track of the allocations in it.
+    /// the memory isn't released, the allocations stay valid until it is. see
+    /// [BuddyAllocator::from_parts] for putting the allocator back together.
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocInit, AllocRef, Global, Layout};
+    /// use buddy_allocator::BuddyAllocator;
+    ///
+    /// let allocator = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+    /// let layout = Layout::from_size_align(32, 16).unwrap();
+    /// let memory = (&allocator).alloc(layout, AllocInit::Zeroed).unwrap();
+    ///
+    /// let (ptr, memory_layout, buddies) = allocator.into_raw_parts();
+    /// assert_eq!(memory_layout.size(), 256);
+    /// assert_eq!(buddies.num_allocations(), 1);
+    /// assert_eq!(unsafe { *memory.ptr.as_ptr() }, 0);
+    ///
+    /// let allocator = unsafe { BuddyAllocator::from_parts(ptr, memory_layout, buddies, Global) };
+    /// unsafe { (&allocator).dealloc(memory.ptr, layout) };
+    /// # #[cfg(feature = "quarantine")] allocator.flush_quarantine();
+    /// let (_, _, buddies) = allocator.into_raw_parts();
+    /// assert!(buddies.is_unused());
+    /// # unsafe { Global.dealloc(ptr, memory_layout) };
+    /// ```
+    pub fn into_raw_parts(self) -> (NonNull<u8>, Layout, Buddies<AR>) {
+        let this = ManuallyDrop::new(self);
+        // the allocator is never dropped, so the buddies are only moved out once
+        let buddies = unsafe { ptr::read(&this.buddies) };
+        (this.memory.ptr, this.layout, buddies)
+    }
+
+    /// put an allocator back together, see [BuddyAllocator::into_raw_parts]
+    ///
+    /// the allocator owns the memory and releases it with `allocator` when it's dropped.
+    ///
+    /// # Safety
+    /// `ptr` must be allocated with `allocator` and `layout` and must not be used by anything
+    /// but the allocations of `buddies`. the parts of an allocator created with
+    /// [BuddyAllocator::from_raw_parts] can't be put back together this way, their memory wasn't
+    /// allocated with `allocator`.
+    pub unsafe fn from_parts(
+        ptr: NonNull<u8>,
+        layout: Layout,
+        buddies: Buddies<AR>,
+        allocator: AR,
+    ) -> Self {
+        BuddyAllocator {
+            allocator,
+            memory: MemoryBlock {
+                ptr,
+                size: layout.size(),
+            },
+            layout,
+            buddies,
+            align: layout.align(),
+            owns_memory: true,
+        }
+    }
+
     /// get the base ptr
     /// ```
     /// #![feature(allocator_api)]
@@ -104,6 +329,74 @@ impl<AR: AllocRef + Copy> BuddyAllocator<AR> {
         self.buddies.capacity()
     }
 
+    /// release the deallocated memory held back by the quarantine
+    ///
+    /// see [Buddies::flush_quarantine](crate::Buddies::flush_quarantine)
+    #[cfg(feature = "quarantine")]
+    pub fn flush_quarantine(&self) {
+        self.buddies.flush_quarantine()
+    }
+
+    /// the biggest alignment an allocation can have
+    fn max_align(&self) -> usize {
+        self.align.min(self.capacitiy())
+    }
+
+    /// whether `ptr` points into the memory handed out by the allocator
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::Global;
+    /// use buddy_allocator::BuddyAllocator;
+    /// use core::ptr::NonNull;
+    ///
+    /// let allocator = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+    /// let base = allocator.base_ptr().as_ptr();
+    /// let ptr = |offset| NonNull::new(base.wrapping_add(offset)).unwrap();
+    /// assert!(allocator.owns(ptr(0)));
+    /// assert!(allocator.owns(ptr(255)));
+    /// assert!(!allocator.owns(ptr(256)));
+    /// assert!(!allocator.owns(NonNull::new(base.wrapping_sub(1)).unwrap()));
+    /// assert!(!allocator.owns(NonNull::dangling()));
+    /// ```
+    ///
+    /// in debug builds deallocating memory the allocator doesn't own panics
+    /// ```should_panic
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocInit, AllocRef, Global, Layout};
+    /// use buddy_allocator::BuddyAllocator;
+    ///
+    /// let first = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+    /// let second = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+    /// let layout = Layout::from_size_align(16, 16).unwrap();
+    /// let memory = (&first).alloc(layout, AllocInit::Uninitialized).unwrap();
+    /// unsafe { (&second).dealloc(memory.ptr, layout) };
+    /// ```
+    pub fn owns(&self, ptr: NonNull<u8>) -> bool {
+        self.offset_of(ptr).is_some()
+    }
+
+    /// the index of `ptr` in the buddies, `None` if the allocator doesn't own it
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocInit, AllocRef, Global, Layout};
+    /// use buddy_allocator::BuddyAllocator;
+    ///
+    /// let allocator = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+    /// let layout = Layout::from_size_align(16, 16).unwrap();
+    /// let first = (&allocator).alloc(layout, AllocInit::Uninitialized).unwrap();
+    /// let second = (&allocator).alloc(layout, AllocInit::Uninitialized).unwrap();
+    /// assert_eq!(allocator.offset_of(first.ptr), Some(0));
+    /// assert_eq!(allocator.offset_of(second.ptr), Some(16));
+    ///
+    /// let end = allocator.base_ptr().as_ptr().wrapping_add(allocator.capacitiy());
+    /// assert_eq!(allocator.offset_of(core::ptr::NonNull::new(end).unwrap()), None);
+    /// ```
+    pub fn offset_of(&self, ptr: NonNull<u8>) -> Option<usize> {
+        (ptr.as_ptr() as usize)
+            .checked_sub(self.base_ptr().as_ptr() as usize)
+            .filter(|&offset| offset < self.capacitiy())
+    }
+
     /// try to allocate the memory at the given ptr
     pub fn allocate_at(
         &self,
@@ -111,6 +404,9 @@ impl<AR: AllocRef + Copy> BuddyAllocator<AR> {
         layout: Layout,
         init: AllocInit,
     ) -> Result<MemoryBlock, AllocErr> {
+        if layout.align() > self.max_align() {
+            return Err(AllocErr);
+        }
         let offset = unsafe {
             ptr.as_ptr()
                 .offset_from(self.base_ptr().as_ptr())
@@ -138,6 +434,10 @@ impl<AR: AllocRef + Copy> BuddyAllocator<AR> {
 
 unsafe impl<AR: AllocRef + Copy> AllocRef for &BuddyAllocator<AR> {
     fn alloc(&mut self, layout: Layout, init: AllocInit) -> Result<MemoryBlock, AllocErr> {
+        if layout.align() > self.max_align() {
+            return Err(AllocErr);
+        }
+
         // try to allocate address space
         let offset = self
             .buddies
@@ -163,6 +463,7 @@ unsafe impl<AR: AllocRef + Copy> AllocRef for &BuddyAllocator<AR> {
     }
 
     unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        debug_assert!(self.owns(ptr), "{:p} isn't owned by the allocator", ptr);
         let offset = ptr
             .as_ptr()
             .offset_from(self.base_ptr().as_ptr())
@@ -171,6 +472,89 @@ unsafe impl<AR: AllocRef + Copy> AllocRef for &BuddyAllocator<AR> {
         self.buddies.deallocate(offset, layout.size());
     }
 
+    /// with `MayMove` the block can merge with a free buddy in front of it, the payload is moved
+    /// to the start of the merged block
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocInit, AllocRef, Global, Layout, ReallocPlacement};
+    /// use buddy_allocator::BuddyAllocator;
+    ///
+    /// let allocator = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+    /// let layout = Layout::from_size_align(16, 16).unwrap();
+    /// let mut a = &allocator;
+    /// unsafe {
+    ///     let first = a.alloc(layout, AllocInit::Uninitialized).unwrap();
+    ///     let second = a.alloc(layout, AllocInit::Uninitialized).unwrap();
+    ///     for i in 0..16 {
+    ///         *second.ptr.as_ptr().add(i) = i as u8;
+    ///     }
+    ///     a.dealloc(first.ptr, layout);
+    ///     # #[cfg(feature = "quarantine")] a.flush_quarantine();
+    ///
+    ///     let grown = a
+    ///         .grow(second.ptr, layout, 24, ReallocPlacement::MayMove, AllocInit::Zeroed)
+    ///         .unwrap();
+    ///     assert_eq!(grown.ptr, first.ptr);
+    ///     assert_eq!(grown.size, 32);
+    ///     let bytes = core::slice::from_raw_parts(grown.ptr.as_ptr(), grown.size);
+    ///     assert!(bytes[..16].iter().copied().eq(0..16));
+    ///     assert!(bytes[16..].iter().all(|&b| b == 0));
+    /// }
+    /// ```
+    ///
+    /// with `Zeroed` exactly the bytes behind the payload are zeroed, wherever the block ends up
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocInit, AllocRef, Global, Layout, ReallocPlacement};
+    /// use buddy_allocator::BuddyAllocator;
+    ///
+    /// // (offset of the payload, its size, free offsets in front of it, new size, new offset)
+    /// let cases = [
+    ///     // extended in place
+    ///     (0, 16, 0, 64, 0),
+    ///     // moved down
+    ///     (16, 16, 16, 32, 0),
+    ///     // moved down and extended
+    ///     (16, 16, 16, 64, 0),
+    ///     // moved down by more than one block
+    ///     (48, 16, 48, 64, 0),
+    ///     // moved to a new block
+    ///     (32, 16, 0, 128, 128),
+    /// ];
+    /// for &(offset, size, free, new_size, new_offset) in cases.iter() {
+    ///     let allocator = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+    ///     let base = allocator.base_ptr().as_ptr();
+    ///     let layout = Layout::from_size_align(size, 16).unwrap();
+    ///     let mut a = &allocator;
+    ///     unsafe {
+    ///         // fill everything in front of the payload, the parts that are free are released
+    ///         // again afterwards
+    ///         let front = (0..offset / 16)
+    ///             .map(|_| a.alloc(Layout::from_size_align(16, 16).unwrap(), AllocInit::Zeroed))
+    ///             .collect::<Result<Vec<_>, _>>()
+    ///             .unwrap();
+    ///         let memory = a.alloc(layout, AllocInit::Uninitialized).unwrap();
+    ///         assert_eq!(memory.ptr.as_ptr(), base.add(offset));
+    ///         for block in front.iter().take(free / 16) {
+    ///             a.dealloc(block.ptr, Layout::from_size_align(16, 16).unwrap());
+    ///             # #[cfg(feature = "quarantine")] a.flush_quarantine();
+    ///         }
+    ///         base.write_bytes(0xff, allocator.capacitiy());
+    ///         for i in 0..size {
+    ///             *memory.ptr.as_ptr().add(i) = i as u8;
+    ///         }
+    ///
+    ///         let grown = a
+    ///             .grow(memory.ptr, layout, new_size, ReallocPlacement::MayMove, AllocInit::Zeroed)
+    ///             .unwrap();
+    ///         assert_eq!(grown.ptr.as_ptr(), base.add(new_offset));
+    ///         assert_eq!(grown.size, new_size);
+    ///         let bytes = core::slice::from_raw_parts(grown.ptr.as_ptr(), grown.size);
+    ///         assert!(bytes[..size].iter().copied().eq(0..size as u8));
+    ///         assert!(bytes[size..].iter().all(|&b| b == 0));
+    ///     }
+    /// }
+    /// ```
     unsafe fn grow(
         &mut self,
         ptr: NonNull<u8>,
@@ -179,60 +563,133 @@ unsafe impl<AR: AllocRef + Copy> AllocRef for &BuddyAllocator<AR> {
         placement: ReallocPlacement,
         init: AllocInit,
     ) -> Result<MemoryBlock, AllocErr> {
+        if layout.align() > self.max_align() {
+            return Err(AllocErr);
+        }
+
         // try growing the memory
         let offset = ptr
             .as_ptr()
             .offset_from(self.base_ptr().as_ptr())
             .try_into()
             .unwrap();
-        let new_offset = self
-            .buddies
-            .grow(offset, layout.size(), new_size, placement)
-            .ok_or(AllocErr)?;
+        let new_offset =
+            match self
+                .buddies
+                .grow_by_merging(offset, layout.size(), new_size, placement)
+            {
+                Some(new_offset) => new_offset,
+                None => {
+                    if let ReallocPlacement::InPlace = placement {
+                        return Err(AllocErr);
+                    }
+
+                    // move the memory to a new block, the old block is only released after copying
+                    let new_layout =
+                        Layout::from_size_align(new_size, layout.align()).map_err(|_| AllocErr)?;
+                    let memory = self.alloc(new_layout, AllocInit::Uninitialized)?;
+                    copy_nonoverlapping(ptr.as_ptr(), memory.ptr.as_ptr(), layout.size());
+                    initialize_grown_memory_block(&memory, layout.size(), init);
+                    self.dealloc(ptr, layout);
+                    return Ok(memory);
+                }
+            };
         let new_size = self.buddies.real_size_for_allocation(new_size);
 
-        // re-initialize the memory
+        // merging with a buddy in front of the block moves it down, the new block contains the
+        // old one, so the payload is moved with an overlapping copy
         let new_ptr = self.base_ptr().as_ptr().add(new_offset);
         let new_ptr = NonNull::new(new_ptr).unwrap();
-        if let AllocInit::Zeroed = init {
-            let old_size = layout.size();
-            let old_ptr = ptr;
-
-            let old_start = old_ptr.as_ptr();
-            let old_end = old_start.add(old_size);
-            let new_start = new_ptr.as_ptr();
-            let new_end = new_start.add(new_size);
-
-            // initialize memory in front of the old memory
-            if new_start < old_start {
-                let offset = old_start.offset_from(new_start).try_into().unwrap();
-                new_ptr.as_ptr().write_bytes(0, offset);
-            }
-
-            // initialize memory behind the old memory
-            if new_end > old_end {
-                let offset = old_end.offset_from(new_end).try_into().unwrap();
-                old_end.write_bytes(0, offset);
-            }
+        if new_offset != offset {
+            copy(ptr.as_ptr(), new_ptr.as_ptr(), layout.size());
         }
 
         // update memory
-        let layout = Layout::from_size_align(new_size, layout.align()).unwrap();
         let memory = MemoryBlock {
             ptr: new_ptr,
-            size: layout.size(),
+            size: new_size,
         };
+        initialize_grown_memory_block(&memory, layout.size(), init);
 
         Ok(memory)
     }
 
+    /// with `MayMove` the memory is moved into a free block of the new size if there is one
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocInit, AllocRef, Global, Layout, ReallocPlacement};
+    /// use buddy_allocator::BuddyAllocator;
+    ///
+    /// let allocator = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+    /// let small = Layout::from_size_align(16, 16).unwrap();
+    /// let big = Layout::from_size_align(64, 16).unwrap();
+    /// let mut a = &allocator;
+    /// unsafe {
+    ///     let first = a.alloc(small, AllocInit::Uninitialized).unwrap();
+    ///     let second = a.alloc(small, AllocInit::Uninitialized).unwrap();
+    ///     let memory = a.alloc(big, AllocInit::Zeroed).unwrap();
+    ///     memory.ptr.as_ptr().write_bytes(7, 16);
+    ///     // the second block of 16 is free
+    ///     a.dealloc(second.ptr, small);
+    ///     # #[cfg(feature = "quarantine")] a.flush_quarantine();
+    ///
+    ///     let moved = a.shrink(memory.ptr, big, 16, ReallocPlacement::MayMove).unwrap();
+    ///     # #[cfg(feature = "quarantine")] a.flush_quarantine();
+    ///     assert_eq!(moved.ptr, second.ptr);
+    ///     assert_eq!(*moved.ptr.as_ptr(), 7);
+    ///     // the old block is free again
+    ///     let again = a.alloc(big, AllocInit::Uninitialized).unwrap();
+    ///     assert_eq!(again.ptr, memory.ptr);
+    ///
+    ///     let kept = a.shrink(memory.ptr, big, 16, ReallocPlacement::InPlace).unwrap();
+    ///     assert_eq!(kept.ptr, memory.ptr);
+    ///     # let _ = first;
+    /// }
+    /// ```
     unsafe fn shrink(
         &mut self,
         ptr: NonNull<u8>,
         layout: Layout,
         new_size: usize,
-        _: ReallocPlacement,
+        placement: ReallocPlacement,
     ) -> Result<MemoryBlock, AllocErr> {
+        if layout.align() > self.max_align() {
+            return Err(AllocErr);
+        }
+
+        // like `Global`, shrinking to 0 frees the memory if it may move
+        if new_size == 0 {
+            if let ReallocPlacement::MayMove = placement {
+                self.dealloc(ptr, layout);
+                return Ok(MemoryBlock {
+                    ptr: NonNull::new_unchecked(layout.align() as *mut u8),
+                    size: 0,
+                });
+            }
+        }
+
+        // a block for size 0 has the same size as one for size 1
+        let new_size = self.buddies.real_size_for_allocation(new_size);
+
+        // move the memory into a free block of the new size, so the old block can merge again.
+        // the old block is only released after copying
+        if let ReallocPlacement::MayMove = placement {
+            if new_size < self.buddies.real_size_for_allocation(layout.size()) {
+                if let Some(new_offset) = self
+                    .buddies
+                    .allocate_without_splitting(new_size, layout.align())
+                {
+                    let new_ptr = NonNull::new_unchecked(self.base_ptr().as_ptr().add(new_offset));
+                    copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_ptr(), new_size);
+                    self.dealloc(ptr, layout);
+                    return Ok(MemoryBlock {
+                        ptr: new_ptr,
+                        size: new_size,
+                    });
+                }
+            }
+        }
+
         // shrink in place
         let offset = ptr
             .as_ptr()
@@ -240,7 +697,6 @@ unsafe impl<AR: AllocRef + Copy> AllocRef for &BuddyAllocator<AR> {
             .try_into()
             .unwrap();
         self.buddies.shrink(offset, layout.size(), new_size);
-        let new_size = self.buddies.real_size_for_allocation(new_size);
 
         // update memory
         let layout = Layout::from_size_align(new_size, layout.align()).unwrap();
@@ -255,8 +711,10 @@ unsafe impl<AR: AllocRef + Copy> AllocRef for &BuddyAllocator<AR> {
 
 impl<AR: AllocRef> Drop for BuddyAllocator<AR> {
     fn drop(&mut self) {
-        unsafe {
-            self.allocator.dealloc(self.memory.ptr, self.layout);
+        if self.owns_memory {
+            unsafe {
+                self.allocator.dealloc(self.memory.ptr, self.layout);
+            }
         }
     }
 }
@@ -266,3 +724,11 @@ unsafe fn initialize_memory_block(block: &mut MemoryBlock, init: AllocInit) {
         write_bytes(block.ptr.as_ptr(), 0, block.size)
     }
 }
+
+/// initialize `[payload, block.size)` of a grown block, the payload was already moved to the
+/// start of the block
+unsafe fn initialize_grown_memory_block(block: &MemoryBlock, payload: usize, init: AllocInit) {
+    if let AllocInit::Zeroed = init {
+        write_bytes(block.ptr.as_ptr().add(payload), 0, block.size - payload)
+    }
+}
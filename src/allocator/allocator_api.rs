@@ -0,0 +1,191 @@
+//! the allocator api of the standard library
+//!
+//! the returned slices cover the whole block, so callers can use the rounded up size.
+
+use super::BuddyAllocator;
+use alloc_wg::alloc::{AllocInit, AllocRef, ReallocPlacement};
+use core::{
+    alloc::{AllocError, Allocator, Layout},
+    ptr::{copy_nonoverlapping, NonNull},
+};
+
+impl<AR: AllocRef + Copy> BuddyAllocator<AR> {
+    fn offset_of_ptr(&self, ptr: NonNull<u8>) -> usize {
+        ptr.as_ptr() as usize - self.base_ptr().as_ptr() as usize
+    }
+
+    fn allocate_with(&self, layout: Layout, init: AllocInit) -> Result<NonNull<[u8]>, AllocError> {
+        // the buddies panic for sizes and alignments they can never satisfy
+        let size = self
+            .buddies
+            .try_real_size_for_allocation(layout.size())
+            .ok_or(AllocError)?;
+        if layout.align() > self.max_align() {
+            return Err(AllocError);
+        }
+        let offset = self
+            .buddies
+            .allocate(layout.size(), layout.align())
+            .ok_or(AllocError)?;
+        let ptr = unsafe { NonNull::new_unchecked(self.base_ptr().as_ptr().add(offset)) };
+        if let AllocInit::Zeroed = init {
+            unsafe { ptr.as_ptr().write_bytes(0, size) };
+        }
+        Ok(NonNull::slice_from_raw_parts(ptr, size))
+    }
+
+    /// merge the block with its buddies if it stays where it is, otherwise move it
+    unsafe fn grow_with(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        init: AllocInit,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new_size = self
+            .buddies
+            .try_real_size_for_allocation(new_layout.size())
+            .ok_or(AllocError)?;
+
+        let aligned = ptr.as_ptr() as usize & (new_layout.align() - 1) == 0;
+        let merged = aligned
+            && self
+                .buddies
+                .grow_by_merging(
+                    self.offset_of_ptr(ptr),
+                    old_layout.size(),
+                    new_layout.size(),
+                    ReallocPlacement::InPlace,
+                )
+                .is_some();
+        if merged {
+            if let AllocInit::Zeroed = init {
+                ptr.as_ptr()
+                    .add(old_layout.size())
+                    .write_bytes(0, new_size - old_layout.size());
+            }
+            return Ok(NonNull::slice_from_raw_parts(ptr, new_size));
+        }
+
+        // the old block is only released after copying
+        let memory = self.allocate_with(new_layout, AllocInit::Uninitialized)?;
+        let new_ptr = memory.cast::<u8>();
+        copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_ptr(), old_layout.size());
+        if let AllocInit::Zeroed = init {
+            new_ptr
+                .as_ptr()
+                .add(old_layout.size())
+                .write_bytes(0, new_size - old_layout.size());
+        }
+        self.deallocate(ptr, old_layout);
+        Ok(memory)
+    }
+}
+
+/// the allocator api of the standard library, `&BuddyAllocator` implements it as well
+///
+/// this needs the `allocator-api` feature.
+#[cfg_attr(
+    feature = "allocator-api",
+    doc = r#"```
+#![feature(allocator_api)]
+use alloc_wg::alloc::Global;
+use buddy_allocator::BuddyAllocator;
+use core::alloc::{Allocator, Layout};
+
+let allocator = BuddyAllocator::try_new(10, 16, None, Global).unwrap();
+let mut vec = Vec::new_in(&allocator);
+for i in 0..1000u32 {
+    vec.push(i);
+}
+assert!(vec.iter().copied().eq(0..1000));
+drop(vec);
+
+// the whole block can be used
+let layout = Layout::from_size_align(20, 4).unwrap();
+let memory = allocator.allocate(layout).unwrap();
+assert_eq!(memory.len(), 32);
+
+// only the bytes behind the old allocation are zeroed
+let ptr = memory.cast::<u8>();
+unsafe {
+    ptr.as_ptr().write_bytes(7, 32);
+    let new_layout = Layout::from_size_align(64, 4).unwrap();
+    let grown = allocator.grow_zeroed(ptr, layout, new_layout).unwrap();
+    let bytes = grown.as_ref();
+    assert!(bytes[..20].iter().all(|&b| b == 7));
+    assert!(bytes[20..].iter().all(|&b| b == 0));
+    allocator.deallocate(grown.cast(), new_layout);
+}
+
+# #[cfg(feature = "quarantine")] allocator.flush_quarantine();
+// everything was released
+let all = Layout::from_size_align(allocator.capacitiy(), 1).unwrap();
+assert!(allocator.allocate(all).is_ok());
+
+// sizes and alignments that never fit fail
+let allocator = BuddyAllocator::try_new(5, 16, None, Global).unwrap();
+assert!(allocator.allocate(Layout::from_size_align(4096, 1).unwrap()).is_err());
+assert!(allocator.allocate(Layout::from_size_align(16, 4096).unwrap()).is_err());
+assert!(Vec::<u8, _>::new_in(&allocator).try_reserve(4096).is_err());
+```"#
+)]
+unsafe impl<AR: AllocRef + Copy> Allocator for BuddyAllocator<AR> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate_with(layout, AllocInit::Uninitialized)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.allocate_with(layout, AllocInit::Zeroed)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        debug_assert!(self.owns(ptr), "{:p} isn't owned by the allocator", ptr);
+        self.buddies
+            .deallocate(self.offset_of_ptr(ptr), layout.size());
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.grow_with(ptr, old_layout, new_layout, AllocInit::Uninitialized)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.grow_with(ptr, old_layout, new_layout, AllocInit::Zeroed)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // a block for size 0 has the same size as one for size 1
+        let new_size = self.buddies.real_size_for_allocation(new_layout.size());
+
+        if ptr.as_ptr() as usize & (new_layout.align() - 1) == 0 {
+            self.buddies
+                .shrink(self.offset_of_ptr(ptr), old_layout.size(), new_size);
+            return Ok(NonNull::slice_from_raw_parts(ptr, new_size));
+        }
+
+        // the old block is only released after copying
+        let memory = self.allocate_with(new_layout, AllocInit::Uninitialized)?;
+        copy_nonoverlapping(
+            ptr.as_ptr(),
+            memory.cast::<u8>().as_ptr(),
+            new_layout.size(),
+        );
+        self.deallocate(ptr, old_layout);
+        Ok(memory)
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{raw::RawBuddies, sync::AtomicUsize};
+use alloc_wg::{
+    alloc::{AllocRef, Global, ReallocPlacement},
+    vec::Vec,
+};
+
+/// a collection of buddy trees covering an arbitrary capacity exactly
+///
+/// the capacity is split into power of two sized trees (eg 48 is split into 32 and 16) which are
+/// laid out back to back starting with the biggest one. allocations never span trees.
+pub struct BuddyForest<A: AllocRef = Global> {
+    trees: Vec<RawBuddies<Vec<AtomicUsize, A>>, A>,
+    capacity: usize,
+}
+
+impl BuddyForest<Global> {
+    /// create a new instance with exactly `capacity`
+    ///
+    /// `capacity` must not be zero and be divisable by `multiplier`
+    ///
+    /// # Panics
+    /// panics if:
+    /// - `capacity` is zero or not divisable by `multiplier`
+    /// - `multiplier` is not a power of two
+    /// ```
+    /// use buddy_allocator::BuddyForest;
+    ///
+    /// let forest = BuddyForest::with_capacity(48, 4);
+    /// assert_eq!(forest.capacity(), 48);
+    /// assert_eq!(forest.num_trees(), 2);
+    /// assert_eq!(forest.allocate(32, 1), Some(0));
+    /// assert_eq!(forest.allocate(16, 1), Some(32));
+    /// assert_eq!(forest.allocate(4, 1), None);
+    /// ```
+    pub fn with_capacity(capacity: usize, multiplier: usize) -> Self {
+        BuddyForest::with_capacity_in(capacity, multiplier, Global)
+    }
+}
+
+impl<A: AllocRef> BuddyForest<A> {
+    /// see [BuddyForest::with_capacity](BuddyForest::with_capacity)
+    pub fn with_capacity_in(capacity: usize, multiplier: usize, a: A) -> Self
+    where
+        A: Clone,
+    {
+        assert!(
+            multiplier.is_power_of_two(),
+            "multiplier must be a power of two"
+        );
+        assert_ne!(capacity, 0, "capacity must not be zero");
+        assert_eq!(
+            capacity % multiplier,
+            0,
+            "capacity {} is not a multiple of multiplier {}",
+            capacity,
+            multiplier
+        );
+
+        let mut trees = Vec::with_capacity_in(capacity.count_ones() as usize, a.clone());
+        let mut base = 0;
+        let mut remaining = capacity;
+        while remaining != 0 {
+            // take the biggest power of two first so every tree is aligned to its size
+            let tree_capacity = !(usize::MAX >> 1) >> remaining.leading_zeros();
+            trees.push(RawBuddies::with_capacity_at(
+                base,
+                tree_capacity,
+                multiplier,
+                a.clone(),
+            ));
+            base += tree_capacity;
+            remaining -= tree_capacity;
+        }
+
+        BuddyForest { trees, capacity }
+    }
+
+    /// return the capacity
+    /// ```
+    /// use buddy_allocator::BuddyForest;
+    ///
+    /// let forest = BuddyForest::with_capacity(44, 4);
+    /// assert_eq!(forest.capacity(), 44);
+    /// ```
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// return the number of trees
+    /// ```
+    /// use buddy_allocator::BuddyForest;
+    ///
+    /// let forest = BuddyForest::with_capacity(44, 4);
+    /// assert_eq!(forest.num_trees(), 3);
+    /// ```
+    pub fn num_trees(&self) -> usize {
+        self.trees.len()
+    }
+
+    /// return the number of outstanding allocations
+    ///
+    /// see [Buddies::num_allocations](crate::Buddies::num_allocations)
+    pub fn num_allocations(&self) -> usize {
+        self.trees.iter().map(RawBuddies::num_allocations).sum()
+    }
+
+    /// release the buddies held back by the quarantines of all trees
+    ///
+    /// see [Buddies::flush_quarantine](crate::Buddies::flush_quarantine)
+    #[cfg(feature = "quarantine")]
+    pub fn flush_quarantine(&self) {
+        self.trees.iter().for_each(RawBuddies::flush_quarantine)
+    }
+
+    /// get the real size of an allocation for a given size
+    ///
+    /// see [Buddies::real_size_for_allocation](crate::Buddies::real_size_for_allocation)
+    /// ```
+    /// use buddy_allocator::BuddyForest;
+    ///
+    /// let forest = BuddyForest::with_capacity(48, 4);
+    /// assert_eq!(forest.real_size_for_allocation(0), 4);
+    /// assert_eq!(forest.real_size_for_allocation(5), 8);
+    /// assert_eq!(forest.real_size_for_allocation(17), 32);
+    /// ```
+    pub fn real_size_for_allocation(&self, size: usize) -> usize {
+        self.trees[0].real_size_for_allocation(size)
+    }
+
+    /// get the real size of an allocation for a given size, `None` if no block is big enough
+    ///
+    /// see [Buddies::try_real_size_for_allocation](crate::Buddies::try_real_size_for_allocation)
+    pub fn try_real_size_for_allocation(&self, size: usize) -> Option<usize> {
+        self.trees[0].try_real_size_for_allocation(size)
+    }
+
+    fn tree(&self, idx: usize) -> &RawBuddies<Vec<AtomicUsize, A>> {
+        self.trees
+            .iter()
+            .find(|tree| idx >= tree.base() && idx - tree.base() < tree.capacity())
+            .unwrap_or_else(|| panic!("idx {} is out of bounds", idx))
+    }
+
+    /// try to allocate a buddy with a given size at a given index
+    ///
+    /// see [Buddies::allocate_at](crate::Buddies::allocate_at)
+    /// ```
+    /// use buddy_allocator::BuddyForest;
+    ///
+    /// let forest = BuddyForest::with_capacity(48, 4);
+    /// assert!(forest.allocate_at(16, 32));
+    /// assert!(!forest.allocate_at(4, 44));
+    /// assert!(forest.allocate_at(4, 4));
+    /// ```
+    pub fn allocate_at(&self, size: usize, idx: usize) -> bool {
+        let tree = self.tree(idx);
+        if size > tree.capacity() {
+            return false;
+        }
+        tree.allocate_at_with_size(size, idx)
+    }
+
+    /// allocate a buddy with a given size
+    ///
+    /// the trees are tried in order of their indices.
+    ///
+    /// see [Buddies::allocate](crate::Buddies::allocate)
+    /// ```
+    /// use buddy_allocator::BuddyForest;
+    ///
+    /// let forest = BuddyForest::with_capacity(7, 1);
+    /// assert_eq!(forest.allocate(2, 1), Some(0));
+    /// assert_eq!(forest.allocate(2, 1), Some(2));
+    /// assert_eq!(forest.allocate(2, 1), Some(4));
+    /// assert_eq!(forest.allocate(1, 1), Some(6));
+    /// assert_eq!(forest.allocate(1, 1), None);
+    /// ```
+    pub fn allocate(&self, size: usize, align: usize) -> Option<usize> {
+        self.trees
+            .iter()
+            .filter(|tree| size <= tree.capacity() && align <= tree.capacity())
+            .find_map(|tree| tree.allocate_with_size(size, align))
+    }
+
+    /// deallocate a buddy with a given size
+    ///
+    /// see [Buddies::deallocate](crate::Buddies::deallocate)
+    /// ```
+    /// use buddy_allocator::BuddyForest;
+    ///
+    /// let forest = BuddyForest::with_capacity(12, 1);
+    /// let idx1 = forest.allocate(8, 1).unwrap();
+    /// let idx2 = forest.allocate(4, 1).unwrap();
+    /// forest.deallocate(idx2, 4);
+    /// forest.deallocate(idx1, 8);
+    /// # #[cfg(feature = "quarantine")] forest.flush_quarantine();
+    /// assert_eq!(forest.num_allocations(), 0);
+    /// ```
+    pub fn deallocate(&self, idx: usize, size: usize) {
+        self.tree(idx).deallocate_with_size(idx, size);
+    }
+
+    /// get the size of the buddy allocated at a given index
+    ///
+    /// see [Buddies::allocation_size_at](crate::Buddies::allocation_size_at)
+    pub fn allocation_size_at(&self, idx: usize) -> Option<usize> {
+        if idx >= self.capacity {
+            return None;
+        }
+        self.tree(idx).allocation_size_at(idx)
+    }
+
+    /// shrink a buddy
+    ///
+    /// see [Buddies::shrink](crate::Buddies::shrink)
+    pub fn shrink(&self, idx: usize, old_size: usize, new_size: usize) {
+        self.tree(idx).shrink_with_size(idx, old_size, new_size)
+    }
+
+    /// grow a buddy
+    ///
+    /// fails if the grown buddy doesn't fit into the tree containing `idx`, with `MayMove` the
+    /// allocation only moves within that tree.
+    ///
+    /// see [Buddies::grow](crate::Buddies::grow)
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::ReallocPlacement;
+    /// use buddy_allocator::BuddyForest;
+    ///
+    /// let forest = BuddyForest::with_capacity(12, 1);
+    /// assert!(forest.allocate_at(2, 8));
+    /// assert_eq!(forest.grow(8, 2, 4, ReallocPlacement::InPlace), Some(8));
+    /// assert_eq!(forest.grow(8, 4, 8, ReallocPlacement::MayMove), None);
+    /// ```
+    pub fn grow(
+        &self,
+        idx: usize,
+        old_size: usize,
+        new_size: usize,
+        placement: ReallocPlacement,
+    ) -> Option<usize> {
+        let tree = self.tree(idx);
+        if new_size > tree.capacity() {
+            return None;
+        }
+        tree.grow_or_move_with_size(idx, old_size, new_size, placement)
+    }
+}
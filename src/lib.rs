@@ -1,20 +1,116 @@
 #![no_std]
-#![feature(allocator_api)]
-#![feature(ptr_offset_from)]
+#![cfg_attr(feature = "static", feature(generic_const_exprs))]
+#![cfg_attr(feature = "static", allow(incomplete_features))]
+#![cfg_attr(feature = "allocator-api", feature(allocator_api))]
+
+#[cfg(all(loom, feature = "static"))]
+compile_error!("loom atomics can't be created in const fns, so the static feature is unsupported");
 
 mod allocator;
+mod forest;
 mod raw;
+mod sharded;
+#[cfg(feature = "static")]
+mod static_buddies;
+mod sync;
+pub mod unsync;
 
-pub use allocator::BuddyAllocator;
+pub use allocator::{BuddyAllocator, BuddyAllocatorError};
+pub use forest::BuddyForest;
+#[cfg(feature = "static")]
+pub use raw::words;
+pub use raw::{
+    AllocFailure, BuildError, CompactionMove, CompactionPlan, ExportError, ImportError,
+    IntegrityError, MergeError, Placement, ScanStart, TryNewError,
+};
+pub use sharded::ShardedBuddies;
+#[cfg(feature = "static")]
+pub use static_buddies::StaticBuddies;
+pub use sync::{Atomic, AtomicInt, Atomics, Backend};
 
-use alloc_wg::alloc::{AllocRef, Global, ReallocPlacement};
+use alloc_wg::{
+    alloc::{AllocRef, Global, ReallocPlacement},
+    vec::Vec,
+};
+use core::{mem, ptr};
 use raw::RawBuddies;
+use sync::Ordering;
+
+/// a handler called when an allocation fails, see [Buddies::set_oom_handler]
+pub type OomHandler<A = Global, B = Atomics> = fn(&GenericBuddies<A, B>, usize, usize) -> bool;
 
-pub struct Buddies<A: AllocRef = Global> {
-    raw: RawBuddies<A>,
+/// a handler called with big free blocks, see [Buddies::with_decommit_handler]
+pub type DecommitHandler = fn(usize, usize);
+
+/// proof that an instance was retired by [Buddies::try_retire]
+///
+/// the instance won't hand out any index again, so whatever it managed can be released.
+#[must_use]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retired {
+    base: usize,
+    capacity: usize,
+}
+
+impl Retired {
+    /// return the first index the retired instance managed
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// return the capacity of the retired instance
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// buddies shared between threads, see [GenericBuddies]
+pub type Buddies<A = Global> = GenericBuddies<A, Atomics>;
+
+/// buddies storing their state in `B`
+///
+/// use [Buddies] or [unsync::Buddies] instead of naming the backend.
+pub struct GenericBuddies<A: AllocRef = Global, B: Backend = Atomics> {
+    raw: RawBuddies<Vec<B::Usize, A>, B>,
+    /// an `OomHandler<A, B>` or null
+    oom_handler: B::Ptr,
+}
+
+/// snapshot the allocation state
+///
+/// the clone is an independent instance with a copy of every block, allocations made or
+/// deallocated in one of them don't affect the other one. the snapshot is only consistent if
+/// there are no concurrent modifications while it's made.
+/// ```
+/// use buddy_allocator::Buddies;
+///
+/// let buddies = Buddies::new(5, 1, None);
+/// let idx1 = buddies.allocate(4, 1).unwrap();
+/// let idx2 = buddies.allocate(2, 1).unwrap();
+///
+/// let snapshot = buddies.clone();
+/// buddies.deallocate(idx1, 4);
+/// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+/// let idx3 = snapshot.allocate(8, 1).unwrap();
+/// snapshot.deallocate(idx2, 2);
+/// # #[cfg(feature = "quarantine")] snapshot.flush_quarantine();
+///
+/// assert_eq!(buddies.num_allocations(), 1);
+/// assert_eq!(snapshot.num_allocations(), 2);
+/// assert_eq!(buddies.allocate(8, 1), Some(idx3));
+/// assert!(buddies.verify_integrity().is_ok());
+/// assert!(snapshot.verify_integrity().is_ok());
+/// ```
+impl<A: AllocRef + Clone, B: Backend> Clone for GenericBuddies<A, B> {
+    fn clone(&self) -> Self {
+        GenericBuddies {
+            raw: self.raw.clone(),
+            oom_handler: B::Ptr::new(self.oom_handler.load(Ordering::Relaxed)),
+        }
+    }
 }
 
-impl Buddies<Global> {
+impl<B: Backend> GenericBuddies<Global, B> {
     /// create a new instance
     ///
     /// `max_order` determines how many different orders there are.
@@ -32,8 +128,15 @@ impl Buddies<Global> {
     /// let buddies = Buddies::new(3, 1, None);
     /// buddies.allocate(2, 2).unwrap();
     /// ```
+    ///
+    /// the capacity of the whole tree must fit into a word
+    /// ```should_panic
+    /// use buddy_allocator::Buddies;
+    ///
+    /// Buddies::new(usize::BITS as usize, 1, None);
+    /// ```
     pub fn new(max_order: usize, multiplier: usize, max_idx: Option<usize>) -> Self {
-        Buddies::new_in(max_order, multiplier, max_idx, Global)
+        Self::new_in(max_order, multiplier, max_idx, Global)
     }
 
     /// create a new instance with the appropriate `max_order` to fit `capacity`
@@ -48,28 +151,484 @@ impl Buddies<Global> {
     /// assert_eq!(buddies.capacity(), 500);
     /// buddies.allocate(2, 2).unwrap();
     /// ```
+    ///
+    /// only the blocks of the initial layout are marked as free, that's at most one per order
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let capacity = (1 << 20) + (1 << 12) + 3;
+    /// let buddies = Buddies::with_capacity(capacity, 1);
+    /// let mut state = vec![0; buddies.state_size()];
+    /// buddies.export_state(&mut state).unwrap();
+    /// let set_bits: u32 = state[27..].iter().map(|byte| byte.count_ones()).sum();
+    /// assert_eq!(set_bits, 4);
+    ///
+    /// // the free blocks are the powers of two of the capacity in decreasing size
+    /// let mut idx = 0;
+    /// for &size in &[1 << 20, 1 << 12, 2, 1] {
+    ///     assert_eq!(buddies.allocate_largest(), Some((idx, size)));
+    ///     idx += size;
+    /// }
+    /// assert_eq!(buddies.allocate_largest(), None);
+    /// ```
+    ///
+    /// tiny capacities down to a single unit work too
+    /// ```
+    /// use alloc_wg::alloc::ReallocPlacement;
+    /// use buddy_allocator::Buddies;
+    ///
+    /// for &multiplier in &[1, 2, 4, 8] {
+    ///     for capacity in (multiplier..=64 * multiplier).step_by(multiplier) {
+    ///         let buddies = Buddies::with_capacity(capacity, multiplier);
+    ///         assert_eq!(buddies.capacity(), capacity);
+    ///
+    ///         let mut units: Vec<_> = (0..capacity / multiplier)
+    ///             .map(|_| buddies.allocate(multiplier, 1).unwrap())
+    ///             .collect();
+    ///         assert_eq!(buddies.allocate(1, 1), None);
+    ///         units.sort();
+    ///         assert_eq!(units, (0..capacity).step_by(multiplier).collect::<Vec<_>>());
+    ///         for idx in units {
+    ///             buddies.deallocate(idx, multiplier);
+    ///             # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///         }
+    ///
+    ///         let largest = 1 << capacity.ilog2();
+    ///         assert_eq!(buddies.real_size_for_allocation(largest), largest);
+    ///         let idx = buddies.allocate(largest, 1).unwrap();
+    ///         buddies.shrink(idx, largest, multiplier);
+    ///         assert_eq!(
+    ///             buddies.grow(idx, multiplier, largest, ReallocPlacement::InPlace),
+    ///             Some(idx)
+    ///         );
+    ///         buddies.deallocate(idx, largest);
+    ///         # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///         assert!(buddies.is_unused());
+    ///     }
+    /// }
+    /// ```
     pub fn with_capacity(capacity: usize, multiplier: usize) -> Self {
-        Buddies {
-            raw: RawBuddies::with_capacity(capacity, multiplier, Global),
+        Self::from_raw(RawBuddies::with_capacity(capacity, multiplier, Global))
+    }
+
+    /// create a new instance with `capacity` where the given ranges are already allocated
+    ///
+    /// the ranges are given as `(idx, size)`. they are rounded outwards to multiples of
+    /// `multiplier` and ranges that end up sharing an index after rounding are merged. every
+    /// range is split into the fewest possible buddies which are counted as separate allocations,
+    /// they can be freed with [Buddies::deallocate_by_idx](Buddies::deallocate_by_idx).
+    ///
+    /// see [Buddies::with_capacity](Buddies::with_capacity)
+    /// ```
+    /// use buddy_allocator::{Buddies, BuildError};
+    ///
+    /// // [0, 5) and [5, 6) are rounded to [0, 8)
+    /// let buddies = Buddies::with_used_ranges(48, 4, [(16, 16), (5, 1), (0, 5)].iter().copied())
+    ///     .unwrap();
+    /// assert_eq!(buddies.num_allocations(), 2);
+    /// assert_eq!(buddies.allocation_size_at(0), Some(8));
+    /// assert_eq!(buddies.allocation_size_at(16), Some(16));
+    /// assert_eq!(buddies.allocate(8, 1), Some(8));
+    /// assert_eq!(buddies.allocate(16, 1), Some(32));
+    /// buddies.deallocate_by_idx(16);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocate(16, 1), Some(16));
+    ///
+    /// assert_eq!(
+    ///     Buddies::with_used_ranges(48, 4, [(4, 8), (8, 4)].iter().copied()).err(),
+    ///     Some(BuildError::Overlap { idx: 8, size: 4 })
+    /// );
+    /// assert_eq!(
+    ///     Buddies::with_used_ranges(48, 4, [(40, 9)].iter().copied()).err(),
+    ///     Some(BuildError::OutOfBounds { idx: 40, size: 9 })
+    /// );
+    /// ```
+    pub fn with_used_ranges(
+        capacity: usize,
+        multiplier: usize,
+        used: impl Iterator<Item = (usize, usize)>,
+    ) -> Result<Self, BuildError> {
+        let buddies = Self::try_with_capacity_in(capacity, multiplier, Global)?;
+
+        let mut ranges = Vec::new();
+        for (idx, size) in used {
+            match idx.checked_add(size) {
+                Some(end) if end <= capacity => {}
+                _ => return Err(BuildError::OutOfBounds { idx, size }),
+            }
+            if size != 0 {
+                ranges.push((idx, size));
+            }
+        }
+        ranges.sort_unstable();
+
+        let round_down = |idx: usize| idx & !(multiplier - 1);
+        let round_up = |idx: usize| round_down(idx + multiplier - 1);
+        let mut prev_end = 0;
+        let mut current: Option<(usize, usize)> = None;
+        for &(idx, size) in ranges.iter() {
+            if idx < prev_end {
+                return Err(BuildError::Overlap { idx, size });
+            }
+            prev_end = idx + size;
+
+            let (start, end) = (round_down(idx), round_up(idx + size));
+            current = match current {
+                Some((current_start, current_end)) if start < current_end => {
+                    Some((current_start, end))
+                }
+                Some(range) => {
+                    buddies.allocate_range(range);
+                    Some((start, end))
+                }
+                None => Some((start, end)),
+            };
+        }
+        if let Some(range) = current {
+            buddies.allocate_range(range);
         }
+
+        Ok(buddies)
+    }
+
+    /// create a new instance managing the indices `base..base + capacity`
+    ///
+    /// all indices returned and accepted by the instance are absolute, alignment is relative to
+    /// index 0 and not to `base`.
+    ///
+    /// `base` must be divisable by `multiplier`
+    ///
+    /// see [Buddies::with_capacity](Buddies::with_capacity)
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::with_capacity_at(24, 64, 4);
+    /// assert_eq!(buddies.base(), 24);
+    /// assert_eq!(buddies.capacity(), 64);
+    ///
+    /// // blocks of size 16 start at 24, 40, 56 and 72, none of them are aligned to 16
+    /// assert_eq!(buddies.allocate(16, 16), None);
+    /// assert_eq!(buddies.allocate(8, 16), Some(32));
+    /// assert_eq!(buddies.allocate(4, 32), Some(64));
+    /// assert_eq!(buddies.allocation_size_at(32), Some(8));
+    /// assert_eq!(buddies.allocation_size_at(64), Some(4));
+    ///
+    /// let idx = buddies.grow(32, 8, 16, alloc_wg::alloc::ReallocPlacement::MayMove).unwrap();
+    /// assert_eq!(idx, 24);
+    /// buddies.shrink(idx, 16, 4);
+    /// buddies.deallocate(idx, 4);
+    /// buddies.deallocate(64, 4);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert!(buddies.is_unused());
+    ///
+    /// for base in (0..64).step_by(4) {
+    ///     let buddies = Buddies::with_capacity_at(base, 64, 4);
+    ///     for &align in &[4, 8, 16, 32] {
+    ///         while let Some(idx) = buddies.allocate(4, align) {
+    ///             assert_eq!(idx % align, 0);
+    ///             assert!(idx >= base && idx + 4 <= base + 64);
+    ///         }
+    ///     }
+    ///     assert_eq!(buddies.num_allocations(), 16);
+    /// }
+    /// ```
+    pub fn with_capacity_at(base: usize, capacity: usize, multiplier: usize) -> Self {
+        Self::with_capacity_at_in(base, capacity, multiplier, Global)
+    }
+
+    /// restore an instance from a state exported by [Buddies::export_state](Buddies::export_state)
+    ///
+    /// `bytes` must contain exactly the exported state.
+    /// ```
+    /// use buddy_allocator::{Buddies, ImportError, IntegrityError};
+    ///
+    /// let buddies = Buddies::with_capacity_at(0x1000, 0x3000, 0x100);
+    /// let idx1 = buddies.allocate(0x100, 1).unwrap();
+    /// let idx2 = buddies.allocate(0x400, 1).unwrap();
+    ///
+    /// let mut buf = [0; 64];
+    /// let len = buddies.export_state(&mut buf).unwrap();
+    /// let copy = Buddies::import_state(&buf[..len]).unwrap();
+    /// assert_eq!(copy.base(), 0x1000);
+    /// assert_eq!(copy.capacity(), 0x3000);
+    /// assert_eq!(copy.num_allocations(), 2);
+    /// assert_eq!(copy.allocation_size_at(idx2), Some(0x400));
+    /// for _ in 0..8 {
+    ///     assert_eq!(copy.allocate(0x100, 1), buddies.allocate(0x100, 1));
+    /// }
+    /// copy.deallocate(idx1, 0x100);
+    ///
+    /// assert_eq!(Buddies::import_state(&buf[..len - 1]).err(), Some(ImportError::Truncated));
+    /// assert_eq!(Buddies::import_state(&buf[..len + 1]).err(), Some(ImportError::TrailingBytes));
+    /// let mut wrong_version = buf;
+    /// wrong_version[0] = 2;
+    /// assert_eq!(
+    ///     Buddies::import_state(&wrong_version[..len]).err(),
+    ///     Some(ImportError::UnsupportedVersion(2))
+    /// );
+    ///
+    /// // mark both the root and its first child as free
+    /// let buddies = Buddies::new(3, 1, None);
+    /// let len = buddies.export_state(&mut buf).unwrap();
+    /// buf[27] |= 0b10;
+    /// assert!(matches!(
+    ///     Buddies::import_state(&buf[..len]),
+    ///     Err(ImportError::InvalidState(_))
+    /// ));
+    ///
+    /// // mark the last block at the lowest order as free, it starts past the capacity
+    /// let buddies = Buddies::with_capacity(3, 1);
+    /// let len = buddies.export_state(&mut buf).unwrap();
+    /// buf[27] |= 1 << 6;
+    /// assert_eq!(
+    ///     Buddies::import_state(&buf[..len]).err(),
+    ///     Some(ImportError::InvalidState(IntegrityError::OutOfBounds { order: 2, idx: 3 }))
+    /// );
+    /// ```
+    pub fn import_state(bytes: &[u8]) -> Result<Self, ImportError> {
+        Self::import_state_in(bytes, Global)
     }
 }
 
-impl<A: AllocRef> Buddies<A> {
+impl<A: AllocRef, B: Backend> GenericBuddies<A, B> {
+    fn from_raw(raw: RawBuddies<Vec<B::Usize, A>, B>) -> Self {
+        GenericBuddies {
+            raw,
+            oom_handler: B::Ptr::new(ptr::null_mut()),
+        }
+    }
+
     /// see [Buddies::new](Buddies::new)
     pub fn new_in(max_order: usize, multiplier: usize, max_idx: Option<usize>, a: A) -> Self {
-        Buddies {
-            raw: RawBuddies::new_in(max_order, multiplier, max_idx, a),
-        }
+        Self::from_raw(RawBuddies::new_in(max_order, multiplier, max_idx, a))
+    }
+
+    /// create a new instance without panicking
+    ///
+    /// returns an error if the parameters are invalid or allocating the blocks fails, see
+    /// [Buddies::new](Buddies::new)
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocErr, AllocInit, AllocRef, Global, Layout, MemoryBlock};
+    /// use buddy_allocator::{Buddies, TryNewError};
+    /// use core::ptr::NonNull;
+    ///
+    /// struct Failing;
+    ///
+    /// unsafe impl AllocRef for Failing {
+    ///     fn alloc(&mut self, _: Layout, _: AllocInit) -> Result<MemoryBlock, AllocErr> {
+    ///         Err(AllocErr)
+    ///     }
+    ///
+    ///     unsafe fn dealloc(&mut self, _: NonNull<u8>, _: Layout) {
+    ///         unreachable!()
+    ///     }
+    /// }
+    ///
+    /// assert!(Buddies::try_new_in(3, 1, None, Global).is_ok());
+    /// assert!(matches!(
+    ///     Buddies::try_new_in(3, 1, None, Failing),
+    ///     Err(TryNewError::AllocError)
+    /// ));
+    /// assert!(matches!(
+    ///     Buddies::try_new_in(0, 1, None, Global),
+    ///     Err(TryNewError::InvalidParameters(_))
+    /// ));
+    /// assert!(matches!(
+    ///     Buddies::try_new_in(3, 3, None, Global),
+    ///     Err(TryNewError::InvalidParameters(_))
+    /// ));
+    /// assert!(matches!(
+    ///     Buddies::try_new_in(3, 1, Some(2), Global),
+    ///     Err(TryNewError::InvalidParameters(_))
+    /// ));
+    ///
+    /// // the capacity of the whole tree must fit into a word, on every platform
+    /// let bits = usize::BITS as usize;
+    /// assert!(matches!(
+    ///     Buddies::try_new_in(bits, 1, None, Global),
+    ///     Err(TryNewError::InvalidParameters(_))
+    /// ));
+    /// assert!(matches!(
+    ///     Buddies::try_new_in(bits - 1, 4, None, Global),
+    ///     Err(TryNewError::InvalidParameters(_))
+    /// ));
+    /// ```
+    ///
+    /// with the `compact` feature the number of blocks must fit into a `u32`, the multiplier
+    /// doesn't count
+    #[cfg_attr(
+        feature = "compact",
+        doc = r#"```
+#![feature(allocator_api)]
+use alloc_wg::alloc::Global;
+use buddy_allocator::{Buddies, TryNewError};
+
+assert!(matches!(
+    Buddies::try_new_in(34, 1, None, Global),
+    Err(TryNewError::TooManyBlocks { blocks: 0x2_0000_0000 })
+));
+assert!(matches!(
+    Buddies::try_new_in(33, 4096, None, Global),
+    Err(TryNewError::TooManyBlocks { blocks: 0x1_0000_0000 })
+));
+assert!(Buddies::try_new_in(20, 4096, None, Global).is_ok());
+```"#
+    )]
+    pub fn try_new_in(
+        max_order: usize,
+        multiplier: usize,
+        max_idx: Option<usize>,
+        a: A,
+    ) -> Result<Self, TryNewError> {
+        Ok(Self::from_raw(RawBuddies::try_new_in(
+            max_order, multiplier, max_idx, a,
+        )?))
     }
 
     /// see [Buddies::with_capacity](Buddies::with_capacity)
+    ///
+    /// only the blocks starting below the capacity are stored, so the memory used for them is
+    /// proportional to the capacity and not to the size of the tree.
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocErr, AllocInit, AllocRef, Global, Layout, MemoryBlock};
+    /// use buddy_allocator::Buddies;
+    /// use core::{cell::Cell, ptr::NonNull};
+    ///
+    /// struct Counting<'a>(&'a Cell<usize>);
+    ///
+    /// unsafe impl AllocRef for Counting<'_> {
+    ///     fn alloc(&mut self, layout: Layout, init: AllocInit) -> Result<MemoryBlock, AllocErr> {
+    ///         self.0.set(self.0.get() + layout.size());
+    ///         Global.alloc(layout, init)
+    ///     }
+    ///
+    ///     unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+    ///         Global.dealloc(ptr, layout)
+    ///     }
+    /// }
+    ///
+    /// // the tree has room for 8 GiB, but the last 3 GiB are never used
+    /// let bytes = Cell::new(0);
+    /// let capacity = 5 * 1024 * 1024 * 1024 + 4096;
+    /// let buddies = Buddies::with_capacity_in(capacity, 4096, Counting(&bytes));
+    /// let full_tree = 2 * ((1 << 22) - 1) / 8;
+    /// assert!(bytes.get() < full_tree * 2 / 3);
+    ///
+    /// let idx = buddies.allocate(4 * 1024 * 1024 * 1024, 1).unwrap();
+    /// assert_eq!(buddies.allocate(1024 * 1024 * 1024, 1), Some(idx + 4 * 1024 * 1024 * 1024));
+    /// assert_eq!(buddies.allocate(4096, 1), Some(idx + 5 * 1024 * 1024 * 1024));
+    /// assert_eq!(buddies.allocate(4096, 1), None);
+    /// ```
     pub fn with_capacity_in(capacity: usize, multiplier: usize, a: A) -> Self {
-        Buddies {
-            raw: RawBuddies::with_capacity(capacity, multiplier, a),
+        Self::from_raw(RawBuddies::with_capacity(capacity, multiplier, a))
+    }
+
+    /// create a new instance with a given capacity without panicking
+    ///
+    /// see [Buddies::with_capacity](Buddies::with_capacity) and
+    /// [Buddies::try_new_in](Buddies::try_new_in)
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::Global;
+    /// use buddy_allocator::{Buddies, TryNewError};
+    ///
+    /// let buddies = Buddies::try_with_capacity_in(48, 4, Global).unwrap();
+    /// assert_eq!(buddies.capacity(), 48);
+    /// assert!(matches!(
+    ///     Buddies::try_with_capacity_in(48, 5, Global),
+    ///     Err(TryNewError::InvalidParameters(_))
+    /// ));
+    /// assert!(matches!(
+    ///     Buddies::try_with_capacity_in(usize::MAX, 1, Global),
+    ///     Err(TryNewError::InvalidParameters(_))
+    /// ));
+    /// assert!(matches!(
+    ///     Buddies::try_with_capacity_in(3 << (usize::BITS - 2), 1 << (usize::BITS - 2), Global),
+    ///     Err(TryNewError::InvalidParameters(_))
+    /// ));
+    /// ```
+    pub fn try_with_capacity_in(
+        capacity: usize,
+        multiplier: usize,
+        a: A,
+    ) -> Result<Self, TryNewError> {
+        Ok(Self::from_raw(RawBuddies::try_with_capacity(
+            capacity, multiplier, a,
+        )?))
+    }
+
+    /// see [Buddies::with_capacity_at](Buddies::with_capacity_at)
+    pub fn with_capacity_at_in(base: usize, capacity: usize, multiplier: usize, a: A) -> Self {
+        Self::from_raw(RawBuddies::with_capacity_at(base, capacity, multiplier, a))
+    }
+
+    /// see [Buddies::import_state](Buddies::import_state)
+    pub fn import_state_in(bytes: &[u8], a: A) -> Result<Self, ImportError> {
+        Ok(Self::from_raw(RawBuddies::import_state_in(bytes, a)?))
+    }
+
+    /// allocate `start..end` with the fewest buddies, the range must be free and aligned
+    fn allocate_range(&self, (start, end): (usize, usize)) {
+        let mut idx = start;
+        while idx < end {
+            let mut size = self.size_for_order(0);
+            while idx & (size - 1) != 0 || idx + size > end {
+                size >>= 1;
+            }
+            let allocated = self.allocate_at(size, idx);
+            debug_assert!(allocated, "{} with size {} is not free", idx, size);
+            idx += size;
         }
     }
 
+    /// return the number of bytes needed by [Buddies::export_state](Buddies::export_state)
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// assert_eq!(buddies.state_size(), 29);
+    /// let buddies = Buddies::new(10, 1, None);
+    /// assert_eq!(buddies.state_size(), 283);
+    /// ```
+    pub fn state_size(&self) -> usize {
+        self.raw.state_size()
+    }
+
+    /// write the state into `out` and return the number of bytes written
+    ///
+    /// the state can be restored with [Buddies::import_state](Buddies::import_state). it is only
+    /// consistent if there are no concurrent modifications.
+    ///
+    /// the layout starts with a 27 byte header (all integers little endian):
+    /// - version (u8, currently 1)
+    /// - max_order (u8)
+    /// - base_shift (u8)
+    /// - max_idx (u64)
+    /// - base (u64)
+    /// - allocations counter (i64)
+    ///
+    /// which is followed by two bitmaps of `ceil((2^max_order - 1) / 8)` bytes each, the first
+    /// marks free blocks and the second allocated blocks. the blocks are ordered by order and
+    /// then by index, bit `i` of byte `j` belongs to block `8 * j + i`.
+    /// ```
+    /// use buddy_allocator::{Buddies, ExportError};
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// let mut buf = [0; 28];
+    /// assert_eq!(
+    ///     buddies.export_state(&mut buf),
+    ///     Err(ExportError::BufferTooSmall { required: 29 })
+    /// );
+    /// let mut buf = [0; 32];
+    /// assert_eq!(buddies.export_state(&mut buf), Ok(29));
+    /// assert_eq!(&buf[..3], &[1, 3, 0]);
+    /// ```
+    pub fn export_state(&self, out: &mut [u8]) -> Result<usize, ExportError> {
+        self.raw.export_state(out)
+    }
+
     /// return the capacity
     /// ```
     /// use buddy_allocator::Buddies;
@@ -85,9 +644,242 @@ impl<A: AllocRef> Buddies<A> {
         self.raw.capacity()
     }
 
+    /// return the first index
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// assert_eq!(buddies.base(), 0);
+    /// let buddies = Buddies::with_capacity_at(0x100000, 0x4000, 0x1000);
+    /// assert_eq!(buddies.base(), 0x100000);
+    /// ```
+    pub fn base(&self) -> usize {
+        self.raw.base()
+    }
+
+    /// return the number of outstanding allocations
+    ///
+    /// grow and shrink don't change the count. an allocation that is about to fail may be counted
+    /// for a short moment, so concurrent allocations can cause a transient over-count.
+    ///
+    /// returns 0 once [Buddies::is_unused](Buddies::is_unused) returned true.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// assert_eq!(buddies.num_allocations(), 0);
+    /// let idx1 = buddies.allocate(1, 1).unwrap();
+    /// let idx2 = buddies.allocate(2, 1).unwrap();
+    /// assert_eq!(buddies.num_allocations(), 2);
+    /// assert_eq!(buddies.allocate(4, 1), None);
+    /// assert_eq!(buddies.num_allocations(), 2);
+    /// buddies.shrink(idx2, 2, 1);
+    /// assert_eq!(buddies.num_allocations(), 2);
+    /// assert!(buddies.allocate_at(1, 3));
+    /// assert!(!buddies.allocate_at(1, 3));
+    /// assert_eq!(buddies.num_allocations(), 3);
+    /// buddies.deallocate(3, 1);
+    /// buddies.deallocate(idx1, 1);
+    /// buddies.deallocate(idx2, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.num_allocations(), 0);
+    /// assert!(buddies.is_unused());
+    /// assert_eq!(buddies.num_allocations(), 0);
+    /// ```
+    pub fn num_allocations(&self) -> usize {
+        self.raw.num_allocations()
+    }
+
+    /// return the sum of the real sizes of all outstanding allocations
+    ///
+    /// see [Buddies::real_size_for_allocation](Buddies::real_size_for_allocation)
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 4, None);
+    /// let idx = buddies.allocate(5, 1).unwrap();
+    /// assert_eq!(buddies.used_bytes(), 8);
+    /// buddies.shrink(idx, 5, 4);
+    /// assert_eq!(buddies.used_bytes(), 4);
+    /// buddies.deallocate(idx, 4);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.used_bytes(), 0);
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn used_bytes(&self) -> usize {
+        self.raw.used_bytes()
+    }
+
+    /// return the highest value of [Buddies::used_bytes](Buddies::used_bytes) since the instance
+    /// was created or [Buddies::reset_peak](Buddies::reset_peak) was called
+    ///
+    /// the peak is updated after every allocation and grow, so it's never lower than a value of
+    /// `used_bytes` observed before.
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::ReallocPlacement;
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 4, None);
+    /// let idx1 = buddies.allocate(4, 1).unwrap();
+    /// let idx2 = buddies.allocate(16, 1).unwrap();
+    /// buddies.deallocate(idx2, 16);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.peak_used_bytes(), 20);
+    /// let idx1 = buddies.grow(idx1, 4, 32, ReallocPlacement::MayMove).unwrap();
+    /// assert_eq!(buddies.peak_used_bytes(), 32);
+    /// buddies.deallocate(idx1, 32);
+    /// assert_eq!(buddies.peak_used_bytes(), 32);
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn peak_used_bytes(&self) -> usize {
+        self.raw.peak_used_bytes()
+    }
+
+    /// reset the peak to the current [Buddies::used_bytes](Buddies::used_bytes)
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 4, None);
+    /// let idx1 = buddies.allocate(4, 1).unwrap();
+    /// let idx2 = buddies.allocate(16, 1).unwrap();
+    /// buddies.deallocate(idx2, 16);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// buddies.reset_peak();
+    /// assert_eq!(buddies.peak_used_bytes(), 4);
+    /// # let _ = idx1;
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn reset_peak(&self) {
+        self.raw.reset_peak()
+    }
+
+    /// return how often claiming a free block had to be retried because another thread modified
+    /// the same word of the bitmap concurrently
+    ///
+    /// this measures the contention between threads, see
+    /// [Buddies::set_scan_start](Buddies::set_scan_start)
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 1, None);
+    /// buddies.allocate(1, 1).unwrap();
+    /// assert_eq!(buddies.claim_retries(), 0);
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn claim_retries(&self) -> usize {
+        self.raw.claim_retries()
+    }
+
+    /// choose which free buddy allocations are taken from
+    ///
+    /// the default is [Placement::SmallestBlock]. it's best set right after creating the
+    /// instance, but it can be changed at any time.
+    /// ```
+    /// use buddy_allocator::{Buddies, Placement};
+    ///
+    /// // every segment of 16 starts with a long living allocation and has a short living one in
+    /// // the second half. then more long living allocations are made while the short living ones
+    /// // are freed, every now and then a big allocation is tried.
+    /// fn big_allocations(placement: Placement) -> usize {
+    ///     let buddies = Buddies::new(9, 1, None);
+    ///     buddies.set_placement(placement);
+    ///     let mut big = 0;
+    ///     let mut try_big = || {
+    ///         if let Some(idx) = buddies.allocate(8, 1) {
+    ///             big += 1;
+    ///             buddies.deallocate(idx, 8);
+    ///             # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///         }
+    ///     };
+    ///     for segment in (0..256).step_by(16) {
+    ///         assert!(buddies.allocate_at(4, segment));
+    ///         assert!(buddies.allocate_at(2, segment + 8));
+    ///     }
+    ///     for _ in 0..16 {
+    ///         buddies.allocate(2, 1).unwrap();
+    ///         try_big();
+    ///     }
+    ///     for segment in (0..256).step_by(16) {
+    ///         buddies.deallocate(segment + 8, 2);
+    ///         # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///         try_big();
+    ///     }
+    ///     big
+    /// }
+    ///
+    /// // the long living allocations take the free blocks next to the short living ones
+    /// assert_eq!(big_allocations(Placement::SmallestBlock), 0);
+    /// // they are packed into the first three segments, so the second halves of the other ones
+    /// // merge again
+    /// assert_eq!(big_allocations(Placement::Clustered), 13);
+    /// ```
+    pub fn set_placement(&self, placement: Placement) {
+        self.raw.set_placement(placement)
+    }
+
+    /// create the instance with a placement, see [Buddies::set_placement](Buddies::set_placement)
+    /// ```
+    /// use buddy_allocator::{Buddies, Placement};
+    ///
+    /// for &(placement, expected) in &[(Placement::SmallestBlock, 10), (Placement::Clustered, 4)] {
+    ///     let buddies = Buddies::new(5, 1, None).with_placement(placement);
+    ///     assert!(buddies.allocate_at(4, 0));
+    ///     assert!(buddies.allocate_at(2, 8));
+    ///     // 4..8 and 12..16 are free blocks of 4, 10..12 is a free block of 2
+    ///     assert_eq!(buddies.allocate(2, 1), Some(expected));
+    /// }
+    /// ```
+    pub fn with_placement(self, placement: Placement) -> Self {
+        self.set_placement(placement);
+        self
+    }
+
+    /// choose where scans for free blocks start
+    ///
+    /// the default is [ScanStart::Lowest]. with [ScanStart::Striding] concurrent allocations start
+    /// in different regions and only collide when the instance is nearly full, at the cost of
+    /// deterministic placement. use [Buddies::allocate_near](Buddies::allocate_near) to choose
+    /// the region per allocation instead.
+    /// ```
+    /// use buddy_allocator::{Buddies, ScanStart};
+    ///
+    /// let buddies = Buddies::new(5, 1, None);
+    /// for i in 0..16 {
+    ///     assert_eq!(buddies.allocate(1, 1), Some(i));
+    /// }
+    /// for i in (1..16).step_by(2) {
+    ///     buddies.deallocate(i, 1);
+    ///     # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// }
+    ///
+    /// buddies.set_scan_start(ScanStart::Striding);
+    /// let mut found: Vec<_> = (0..8).map(|_| buddies.allocate(1, 1).unwrap()).collect();
+    /// assert_ne!(found[1], 3);
+    /// // every free block is still found
+    /// found.sort();
+    /// assert_eq!(found, (1..16).step_by(2).collect::<Vec<_>>());
+    /// assert_eq!(buddies.allocate(1, 1), None);
+    ///
+    /// buddies.set_scan_start(ScanStart::Lowest);
+    /// buddies.deallocate(5, 1);
+    /// buddies.deallocate(3, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocate(1, 1), Some(3));
+    /// ```
+    pub fn set_scan_start(&self, scan_start: ScanStart) {
+        self.raw.set_scan_start(scan_start)
+    }
+
     /// check if there are any allocations
     /// # Safety
     /// calling this method is equivalent to trying to allocate the entire memory inside at once thus rendering it useless after it returned true
+    ///
+    /// use [Buddies::is_currently_unused](Buddies::is_currently_unused) to check without
+    /// changing anything and [Buddies::try_retire](Buddies::try_retire) for a clearer name.
+    ///
+    /// once it returned true the instance is poisoned for good, every allocation fails and every
+    /// deallocation panics, see [Buddies::is_poisoned](Buddies::is_poisoned)
     /// ```
     /// use buddy_allocator::Buddies;
     ///
@@ -95,13 +887,189 @@ impl<A: AllocRef> Buddies<A> {
     /// let idx = buddies.allocate(1, 1).unwrap();
     /// assert!(!buddies.is_unused());
     /// buddies.deallocate(idx, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
     /// assert!(buddies.is_unused());
     /// ```
     pub fn is_unused(&self) -> bool {
         self.raw.is_unused()
     }
 
+    /// check if there are any allocations without changing anything
+    ///
+    /// concurrent allocations and deallocations might change the result right after it's
+    /// returned, so this is only a snapshot. unlike [Buddies::is_unused](Buddies::is_unused) it
+    /// can be called any time, eg to assert that everything was freed.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// assert!(buddies.is_currently_unused());
+    /// let idx = buddies.allocate(1, 1).unwrap();
+    /// assert!(!buddies.is_currently_unused());
+    /// buddies.deallocate(idx, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert!(buddies.is_currently_unused());
+    /// // the instance is still usable
+    /// assert_eq!(buddies.allocate(1, 1), Some(0));
+    /// ```
+    pub fn is_currently_unused(&self) -> bool {
+        self.raw.num_allocations() == 0
+    }
+
+    /// retire the instance if there are no allocations
+    ///
+    /// this is [Buddies::is_unused](Buddies::is_unused) returning a [Retired] token instead of a
+    /// bool. once it succeeded every allocation fails, so the managed range can be handed back.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::with_capacity_at(64, 32, 1);
+    /// let idx = buddies.allocate(1, 1).unwrap();
+    /// assert_eq!(buddies.try_retire(), None);
+    /// buddies.deallocate(idx, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///
+    /// let retired = buddies.try_retire().unwrap();
+    /// assert_eq!((retired.base(), retired.capacity()), (64, 32));
+    /// assert_eq!(buddies.allocate(1, 1), None);
+    /// // it can only be retired once
+    /// assert_eq!(buddies.try_retire(), None);
+    /// ```
+    pub fn try_retire(&self) -> Option<Retired> {
+        if self.raw.is_unused() {
+            Some(Retired {
+                base: self.base(),
+                capacity: self.capacity(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// check if [Buddies::is_unused](Buddies::is_unused) returned true
+    /// ```
+    /// use buddy_allocator::Buddies;
+    /// use std::panic::{catch_unwind, AssertUnwindSafe};
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// let idx = buddies.allocate(1, 1).unwrap();
+    /// buddies.deallocate(idx, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert!(!buddies.is_poisoned());
+    /// assert!(buddies.is_unused());
+    /// assert!(buddies.is_poisoned());
+    ///
+    /// // no number of failed allocations can undo it
+    /// for _ in 0..1000 {
+    ///     assert_eq!(buddies.allocate(1, 1), None);
+    ///     assert!(!buddies.allocate_at(1, 0));
+    ///     assert_eq!(buddies.allocate_largest(), None);
+    /// }
+    /// assert!(buddies.is_poisoned());
+    /// assert_eq!(buddies.num_allocations(), 0);
+    ///
+    /// // deallocating panics without changing anything
+    /// assert!(catch_unwind(AssertUnwindSafe(|| buddies.deallocate(idx, 1))).is_err());
+    /// assert!(buddies.is_poisoned());
+    /// assert!(!buddies.is_unused());
+    /// assert_eq!(buddies.allocate(1, 1), None);
+    /// ```
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.is_poisoned()
+    }
+
+    /// return the sum of the sizes of the free buddies
+    ///
+    /// this checks every buddy, so it takes O(capacity) steps. it's only exact if there are no
+    /// concurrent modifications.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::with_capacity(48, 4);
+    /// assert_eq!(buddies.free_bytes(), 48);
+    /// let idx = buddies.allocate(5, 1).unwrap();
+    /// assert_eq!(buddies.free_bytes(), 40);
+    /// buddies.deallocate(idx, 5);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.free_bytes(), 48);
+    /// ```
+    pub fn free_bytes(&self) -> usize {
+        self.raw.free_bytes()
+    }
+
+    /// iterate over the free bits of the blocks as `(order, idx, is_free)`
+    ///
+    /// the blocks are ordered by order and then by index, the bits are read while iterating. this
+    /// is meant for inspecting a live instance, use [Buddies::export_state](Buddies::export_state)
+    /// for a consistent copy.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 2, None);
+    /// let idx = buddies.allocate(2, 1).unwrap();
+    /// let free: Vec<_> = buddies
+    ///     .raw_bits()
+    ///     .filter(|&(_, _, is_free)| is_free)
+    ///     .collect();
+    /// assert_eq!(free, [(1, 4, true), (2, 2, true)]);
+    ///
+    /// buddies.deallocate(idx, 2);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.raw_bits().next(), Some((0, 0, true)));
+    /// assert_eq!(buddies.raw_bits().count(), 7);
+    /// ```
+    pub fn raw_bits(&self) -> impl Iterator<Item = (usize, usize, bool)> + '_ {
+        self.raw.bits()
+    }
+
+    /// check every invariant of the buddy tree
+    ///
+    /// this checks that no free or allocated blocks overlap or extend past the capacity, that
+    /// free and allocated blocks cover the capacity and that the counters match the blocks. the
+    /// error names the first violation. this is only meaningful if there are no concurrent
+    /// modifications.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::with_capacity(48, 4);
+    /// assert_eq!(buddies.verify_integrity(), Ok(()));
+    /// let idx = buddies.allocate(5, 1).unwrap();
+    /// assert_eq!(buddies.verify_integrity(), Ok(()));
+    /// buddies.deallocate(idx, 5);
+    /// assert_eq!(buddies.verify_integrity(), Ok(()));
+    /// ```
+    #[cfg_attr(
+        not(feature = "checked-dealloc"),
+        doc = r#"```
+use buddy_allocator::{Buddies, IntegrityError};
+
+let buddies = Buddies::new(3, 1, None);
+let idx1 = buddies.allocate(1, 1).unwrap();
+let idx2 = buddies.allocate(1, 1).unwrap();
+buddies.deallocate(idx1, 1);
+buddies.deallocate(idx2, 1);
+# #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+// free the merged block again
+buddies.deallocate(idx1, 1);
+# #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+assert_eq!(
+    buddies.verify_integrity(),
+    Err(IntegrityError::Overlap { order: 2, idx: 0 })
+);
+```"#
+    )]
+    pub fn verify_integrity(&self) -> Result<(), IntegrityError> {
+        self.raw.check_integrity()
+    }
+
     /// get the real size of an allocation for a given size
+    ///
+    /// the size is rounded up to whole multiples of `multiplier` and then to a power of two, a
+    /// size of zero takes one multiple like any size up to `multiplier`.
+    /// # Panics
+    /// panics if:
+    /// - the real size is bigger than the capacity, see
+    ///   [Buddies::try_real_size_for_allocation](Buddies::try_real_size_for_allocation)
     /// ```
     /// use buddy_allocator::Buddies;
     ///
@@ -123,6 +1091,65 @@ impl<A: AllocRef> Buddies<A> {
         self.raw.real_size_for_allocation(size)
     }
 
+    /// get the real size of an allocation for a given size, `None` if no block is big enough
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 4, None);
+    /// assert_eq!(buddies.try_real_size_for_allocation(5), Some(8));
+    /// assert_eq!(buddies.try_real_size_for_allocation(16), Some(16));
+    /// assert_eq!(buddies.try_real_size_for_allocation(17), None);
+    /// assert_eq!(buddies.try_real_size_for_allocation(usize::MAX), None);
+    ///
+    /// // the biggest block of a capacity that isn't a power of two is smaller than the capacity
+    /// let buddies = Buddies::new(3, 4, Some(12));
+    /// assert_eq!(buddies.try_real_size_for_allocation(8), Some(8));
+    /// assert_eq!(buddies.try_real_size_for_allocation(9), None);
+    /// assert_eq!(buddies.try_real_size_for_allocation(12), None);
+    /// assert_eq!(buddies.try_real_size_for_allocation(13), None);
+    /// ```
+    pub fn try_real_size_for_allocation(&self, size: usize) -> Option<usize> {
+        self.raw.try_real_size_for_allocation(size)
+    }
+
+    /// get the order of the buddies used for allocations of a given size
+    ///
+    /// order 0 is the biggest buddy spanning all of the capacity and every following order halves
+    /// the size, so the smallest buddies have order `max_order - 1`.
+    /// # Panics
+    /// panics if:
+    /// - `size` is bigger than the capacity
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 4, None);
+    /// assert_eq!(buddies.order_for_size(0), 2);
+    /// assert_eq!(buddies.order_for_size(4), 2);
+    /// assert_eq!(buddies.order_for_size(5), 1);
+    /// assert_eq!(buddies.order_for_size(16), 0);
+    /// ```
+    pub fn order_for_size(&self, size: usize) -> usize {
+        self.raw.order_for_size(size)
+    }
+
+    /// get the size of the buddies with a given order
+    ///
+    /// see [Buddies::order_for_size](Buddies::order_for_size)
+    /// # Panics
+    /// panics if:
+    /// - `order` is not less than `max_order`
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 4, Some(12));
+    /// assert_eq!(buddies.size_for_order(0), 16);
+    /// assert_eq!(buddies.size_for_order(1), 8);
+    /// assert_eq!(buddies.size_for_order(2), 4);
+    /// ```
+    pub fn size_for_order(&self, order: usize) -> usize {
+        self.raw.size_for_order(order)
+    }
+
     /// try to allocate a buddy with a given size at a given index
     /// # Panics
     /// panics if:
@@ -145,6 +1172,13 @@ impl<A: AllocRef> Buddies<A> {
     }
 
     /// allocate a buddy with a given size
+    ///
+    /// if this fails the handler set by [Buddies::set_oom_handler](Buddies::set_oom_handler) is
+    /// called
+    ///
+    /// this doesn't recurse, without contention it scans at most `max_order` orders and splits at
+    /// most `max_order - 1` blocks. a search that fails while other threads split or merge blocks
+    /// is repeated after they're done.
     /// # Panics
     /// panics if:
     /// - `size` or `align` are too big
@@ -159,13 +1193,377 @@ impl<A: AllocRef> Buddies<A> {
     /// assert_eq!(buddies.allocate(2, 4).unwrap(), 8);
     /// ```
     pub fn allocate(&self, size: usize, align: usize) -> Option<usize> {
-        self.raw.allocate_with_size(size, align)
+        self.raw.allocate_with_size(size, align).or_else(|| {
+            let handler = self.oom_handler.load(Ordering::Relaxed);
+            if handler.is_null() {
+                return None;
+            }
+            let handler = unsafe { mem::transmute::<*mut (), OomHandler<A, B>>(handler) };
+            if handler(self, size, align) {
+                self.raw.allocate_with_size(size, align)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// allocate a buddy with a given size, explaining why the allocation failed
+    ///
+    /// see [Buddies::allocate](Buddies::allocate), the free memory is only inspected if the
+    /// allocation fails. this is only meaningful if there are no concurrent modifications.
+    /// ```
+    /// use buddy_allocator::{AllocFailure, Buddies};
+    ///
+    /// let buddies = Buddies::new(4, 1, None);
+    /// let units: Vec<_> = (0..8).map(|_| buddies.allocate(1, 1).unwrap()).collect();
+    ///
+    /// // out of memory
+    /// assert_eq!(
+    ///     buddies.allocate_or_explain(1, 1),
+    ///     Err(AllocFailure {
+    ///         free_bytes: 0,
+    ///         largest_free_block: 0,
+    ///     })
+    /// );
+    ///
+    /// // enough free memory, but it's fragmented
+    /// for &idx in units.iter().step_by(2) {
+    ///     buddies.deallocate(idx, 1);
+    ///     # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// }
+    /// assert_eq!(
+    ///     buddies.allocate_or_explain(2, 1),
+    ///     Err(AllocFailure {
+    ///         free_bytes: 4,
+    ///         largest_free_block: 1,
+    ///     })
+    /// );
+    /// assert_eq!(buddies.allocate_or_explain(1, 1), Ok(0));
+    /// ```
+    pub fn allocate_or_explain(&self, size: usize, align: usize) -> Result<usize, AllocFailure> {
+        self.allocate(size, align).ok_or_else(|| AllocFailure {
+            free_bytes: self.raw.free_bytes(),
+            largest_free_block: self.raw.largest_free_block(),
+        })
+    }
+
+    /// allocate a buddy with exactly the given size
+    ///
+    /// unlike [Buddies::allocate](Buddies::allocate) this fails without touching the buddies if
+    /// the size would be rounded up, see [Buddies::is_exact_size](Buddies::is_exact_size)
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 4, None);
+    /// assert_eq!(buddies.allocate_exact(5, 1), None);
+    /// assert_eq!(buddies.num_allocations(), 0);
+    /// assert_eq!(buddies.allocate_exact(8, 1), Some(0));
+    /// assert_eq!(buddies.allocation_size_at(0), Some(8));
+    /// ```
+    pub fn allocate_exact(&self, size: usize, align: usize) -> Option<usize> {
+        if !self.is_exact_size(size) {
+            return None;
+        }
+        self.allocate(size, align)
+    }
+
+    /// check whether allocations of a given size get a buddy of exactly that size
+    ///
+    /// that's the case for the sizes of the buddies, multiples of `multiplier` with a power of
+    /// two as the factor that fit into the capacity.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// for &multiplier in &[1, 2, 4, 8] {
+    ///     let buddies = Buddies::new(5, multiplier, None);
+    ///     for size in 0..=buddies.capacity() + multiplier {
+    ///         let exact = (0..5).any(|order| size == multiplier << order);
+    ///         assert_eq!(buddies.is_exact_size(size), exact, "{}", size);
+    ///     }
+    ///     assert!(!buddies.is_exact_size(usize::MAX));
+    /// }
+    /// ```
+    pub fn is_exact_size(&self, size: usize) -> bool {
+        self.raw.is_exact_size(size)
+    }
+
+    /// plan which allocations to move so that an allocation of `want_size` fits
+    ///
+    /// the plan frees the block needing the fewest moves, then the fewest bytes moved. the
+    /// destinations are free when the plan is made and only aligned to their size. returns `None`
+    /// if `want_size` doesn't fit or there isn't enough free memory outside any block to move its
+    /// allocations. this is only meaningful if there are no concurrent modifications.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let mut seed = 0x2545_f491_u32;
+    /// for &multiplier in &[1, 4] {
+    ///     let buddies = Buddies::new(6, multiplier, None);
+    ///     let unit = multiplier;
+    ///     // fill the capacity and free a pseudo random half of it
+    ///     let mut live = Vec::new();
+    ///     while let Some(idx) = buddies.allocate(unit, 1) {
+    ///         live.push(idx);
+    ///     }
+    ///     live.retain(|&idx| {
+    ///         seed ^= seed << 13;
+    ///         seed ^= seed >> 17;
+    ///         seed ^= seed << 5;
+    ///         if seed % 2 == 0 {
+    ///             buddies.deallocate(idx, unit);
+    ///             # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///         }
+    ///         seed % 2 != 0
+    ///     });
+    ///     let want_size = 8 * unit;
+    ///     assert_eq!(buddies.allocate(want_size, 1), None);
+    ///
+    ///     let plan = buddies.plan_compaction(want_size).unwrap();
+    ///     assert!(!plan.moves().is_empty());
+    ///     for m in plan.moves() {
+    ///         assert!(buddies.allocate_at(m.size, m.to));
+    ///         buddies.deallocate(m.from, m.size);
+    ///         # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///     }
+    ///     assert_eq!(buddies.allocation_size_at(plan.idx()), None);
+    ///     assert_eq!(buddies.allocate(want_size, 1), Some(plan.idx()));
+    ///     assert_eq!(buddies.verify_integrity(), Ok(()));
+    ///
+    ///     // nothing has to move if there's a free block already
+    ///     let plan = buddies.plan_compaction(unit).unwrap();
+    ///     assert!(plan.moves().is_empty());
+    ///     assert!(buddies.plan_compaction(buddies.capacity() + 1).is_none());
+    /// }
+    /// ```
+    pub fn plan_compaction(&self, want_size: usize) -> Option<CompactionPlan> {
+        self.raw.try_real_size_for_allocation(want_size)?;
+        self.raw.plan_compaction(self.raw.order_for_size(want_size))
+    }
+
+    /// set a handler called when [Buddies::allocate](Buddies::allocate) fails
+    ///
+    /// the handler is called with the instance, the size and the alignment of the failed
+    /// allocation. if it returns true the allocation is retried once.
+    ///
+    /// the handler is called after the failed attempt has been rolled back, so it may call any
+    /// method of the instance, eg to deallocate memory. allocations made from inside the handler
+    /// call the handler again if they fail, so it must not rely on them to terminate.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// assert!(buddies.allocate_at(4, 0));
+    /// assert_eq!(buddies.allocate(1, 1), None);
+    ///
+    /// buddies.set_oom_handler(Some(|buddies: &Buddies, size, _align| {
+    ///     assert_eq!(size, 1);
+    ///     buddies.deallocate(0, 4);
+    ///     # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///     true
+    /// }));
+    /// assert_eq!(buddies.allocate(1, 1), Some(0));
+    ///
+    /// buddies.set_oom_handler(Some(|_: &Buddies, _, _| false));
+    /// assert_eq!(buddies.allocate(4, 1), None);
+    ///
+    /// buddies.set_oom_handler(None);
+    /// assert_eq!(buddies.allocate(4, 1), None);
+    /// ```
+    pub fn set_oom_handler(&self, handler: Option<OomHandler<A, B>>) {
+        let handler = handler.map_or(ptr::null_mut(), |handler| handler as *mut ());
+        self.oom_handler.store(handler, Ordering::Relaxed);
+    }
+
+    /// set a handler that's called when a deallocation leaves a free block of at least
+    /// `min_size`, eg to give the memory back to the system
+    ///
+    /// the handler is called with the index and the size of the free block the deallocated buddy
+    /// was merged into, right before the block is marked as free. `min_size` is rounded up like
+    /// the size of an allocation. a block that is merged into a bigger one by a later or
+    /// concurrent deallocation is reported again as part of that one.
+    ///
+    /// nobody can allocate the block before the handler returns, so discarding the contents is
+    /// fine if allocations don't expect them to be zeroed. the memory is handed out again
+    /// afterwards though, so it must stay usable, eg unmapping it isn't. allocations that find no
+    /// other free block wait for the handler.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    /// use std::sync::Mutex;
+    ///
+    /// static FREED: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+    /// fn record(idx: usize, size: usize) {
+    ///     FREED.lock().unwrap().push((idx, size));
+    /// }
+    /// let freed = || std::mem::take(&mut *FREED.lock().unwrap());
+    ///
+    /// let buddies = Buddies::new(5, 1, None).with_decommit_handler(record, 4);
+    /// let idx1 = buddies.allocate(2, 1).unwrap();
+    /// let idx2 = buddies.allocate(2, 1).unwrap();
+    /// let idx3 = buddies.allocate(8, 1).unwrap();
+    ///
+    /// // too small, the buddy of the block is still allocated
+    /// buddies.deallocate(idx1, 2);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(freed(), []);
+    /// // the halves merge into a block of 4, which merges with its free buddy
+    /// buddies.deallocate(idx2, 2);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(freed(), [(0, 8)]);
+    /// // the whole capacity is free
+    /// buddies.deallocate(idx3, 8);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(freed(), [(0, 16)]);
+    ///
+    /// // a block of exactly `min_size` is reported
+    /// let idx1 = buddies.allocate(4, 1).unwrap();
+    /// let idx2 = buddies.allocate(4, 1).unwrap();
+    /// let idx3 = buddies.allocate(8, 1).unwrap();
+    /// buddies.deallocate(idx1, 4);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(freed(), [(idx1, 4)]);
+    /// buddies.deallocate(idx3, 8);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(freed(), [(idx3, 8)]);
+    /// buddies.deallocate(idx2, 4);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(freed(), [(0, 16)]);
+    ///
+    /// // the size is rounded up
+    /// let buddies = Buddies::new(5, 1, None).with_decommit_handler(record, 3);
+    /// let idx = buddies.allocate(2, 1).unwrap();
+    /// buddies.allocate_at(2, 2);
+    /// buddies.deallocate(idx, 2);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(freed(), []);
+    /// ```
+    ///
+    /// the block stays taken while the handler runs
+    /// ```
+    /// use buddy_allocator::Buddies;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::{sync::Arc, thread};
+    ///
+    /// static ENTERED: AtomicBool = AtomicBool::new(false);
+    /// static DONE: AtomicBool = AtomicBool::new(false);
+    /// fn discard(_: usize, _: usize) {
+    ///     ENTERED.store(true, Ordering::SeqCst);
+    ///     while !DONE.load(Ordering::SeqCst) {
+    ///         thread::yield_now();
+    ///     }
+    /// }
+    ///
+    /// let buddies = Arc::new(Buddies::new(3, 1, None).with_decommit_handler(discard, 4));
+    /// let idx = buddies.allocate(4, 1).unwrap();
+    /// let deallocation = {
+    ///     let buddies = buddies.clone();
+    ///     thread::spawn(move || {
+    ///         buddies.deallocate(idx, 4);
+    ///         # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///     })
+    /// };
+    /// while !ENTERED.load(Ordering::SeqCst) {
+    ///     thread::yield_now();
+    /// }
+    ///
+    /// // the block and its parts can't be reallocated before the handler returns
+    /// assert!(!buddies.allocate_at(4, 0));
+    /// assert!(!buddies.allocate_at(1, 2));
+    /// DONE.store(true, Ordering::SeqCst);
+    /// deallocation.join().unwrap();
+    /// assert!(buddies.allocate_at(4, 0));
+    /// ```
+    pub fn with_decommit_handler(mut self, handler: DecommitHandler, min_size: usize) -> Self {
+        let max_order = self.raw.order_for_size(min_size);
+        self.raw.set_decommit_handler(handler, max_order);
+        self
+    }
+
+    /// allocate a buddy with a given order
+    ///
+    /// this is the same as [Buddies::allocate](Buddies::allocate) with
+    /// [Buddies::size_for_order](Buddies::size_for_order) but returns `None` instead of
+    /// panicking if `order` is out of range.
+    /// # Panics
+    /// panics if:
+    /// - `align` is too big
+    /// - `align` is not a power of two
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(4, 1, None);
+    /// // splits the root and a buddy of order 1
+    /// assert_eq!(buddies.allocate_order(2, 1), Some(0));
+    /// assert_eq!(buddies.allocate_order(2, 1), Some(2));
+    /// assert_eq!(buddies.allocate_order(1, 1), Some(4));
+    /// assert_eq!(buddies.allocate_order(3, 1), None);
+    /// assert_eq!(buddies.allocate_order(4, 1), None);
+    /// buddies.deallocate_order(2, 2);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocate_order(3, 1), Some(2));
+    /// assert_eq!(buddies.allocate_order(3, 1), Some(3));
+    /// ```
+    pub fn allocate_order(&self, order: usize, align: usize) -> Option<usize> {
+        self.raw.allocate_order(order, align, None)
+    }
+
+    /// allocate the biggest free buddy and return its index and size
+    ///
+    /// the buddy is claimed as a whole, so unlike trying [Buddies::allocate](Buddies::allocate)
+    /// with decreasing sizes this never splits a buddy. the buddy can be deallocated with the
+    /// returned size.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::with_capacity(48, 4);
+    /// assert_eq!(buddies.allocate_largest(), Some((0, 32)));
+    /// assert!(buddies.allocate_at(4, 32));
+    /// assert_eq!(buddies.allocate_largest(), Some((40, 8)));
+    /// assert_eq!(buddies.allocate_largest(), Some((36, 4)));
+    /// assert_eq!(buddies.allocate_largest(), None);
+    /// buddies.deallocate(0, 32);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.num_allocations(), 3);
+    /// ```
+    pub fn allocate_largest(&self) -> Option<(usize, usize)> {
+        self.raw.allocate_largest()
+    }
+
+    /// allocate a buddy with a given size close to `hint_idx`
+    ///
+    /// the buddy containing `hint_idx` is tried first, then buddies of the same size spiraling
+    /// outwards from it and finally the same places as [Buddies::allocate](Buddies::allocate).
+    /// the hint is only best effort, this fails only if `allocate` would fail too.
+    /// # Panics
+    /// panics if:
+    /// - `size` or `align` are too big
+    /// - `align` is not a power of two
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 4, None);
+    /// // the second half of the memory is used even though the first half is free
+    /// assert_eq!(buddies.allocate_near(4, 4, 40), Some(40));
+    /// assert_eq!(buddies.allocate_near(4, 4, 45), Some(44));
+    /// // the buddy containing the hint is taken, so the closest free one is used
+    /// assert_eq!(buddies.allocate_near(8, 8, 40), Some(32));
+    /// assert_eq!(buddies.allocate_near(16, 1, 60), Some(48));
+    /// assert_eq!(buddies.allocate_near(32, 1, 60), Some(0));
+    /// assert_eq!(buddies.allocate_near(4, 1, 0), None);
+    /// ```
+    pub fn allocate_near(&self, size: usize, align: usize, hint_idx: usize) -> Option<usize> {
+        self.raw
+            .allocate_near_with_size(size, align, Some(hint_idx))
     }
 
     /// deallocate a buddy with a given size
+    ///
+    /// this doesn't recurse, without contention it merges at most `max_order - 1` times. with the
+    /// `quarantine` feature the buddy is only freed later, see
+    /// [Buddies::flush_quarantine](Buddies::flush_quarantine)
     /// # Panics
     /// panics if:
     /// - there is no buddy with that size allocated at that index
+    /// - the instance was poisoned by [Buddies::is_unused](Buddies::is_unused)
     /// ```
     /// use buddy_allocator::Buddies;
     ///
@@ -179,15 +1577,286 @@ impl<A: AllocRef> Buddies<A> {
     /// buddies.deallocate(idx2, 2);
     /// buddies.deallocate(idx3, 2);
     /// ```
+    ///
+    /// with the `checked-dealloc` feature `size` is checked against the size the buddy was
+    /// allocated with, see [Buddies::allocation_size_at](Buddies::allocation_size_at)
+    #[cfg_attr(
+        feature = "checked-dealloc",
+        doc = r#"```should_panic
+use buddy_allocator::Buddies;
+
+let buddies = Buddies::new(5, 1, None);
+let idx1 = buddies.allocate(2, 1).unwrap();
+let idx2 = buddies.allocate(2, 1).unwrap();
+// frees idx1 and idx2 at once without the check
+buddies.deallocate(idx1, 4);
+```"#
+    )]
+    ///
+    /// it also catches freeing a buddy twice, even if it was merged with its buddy in between
+    #[cfg_attr(
+        feature = "checked-dealloc",
+        doc = r#"```should_panic
+use buddy_allocator::Buddies;
+
+let buddies = Buddies::new(5, 1, None);
+let idx1 = buddies.allocate(1, 1).unwrap();
+let idx2 = buddies.allocate(1, 1).unwrap();
+buddies.deallocate(idx1, 1);
+buddies.deallocate(idx2, 1);
+// the free bits can't tell this from a valid deallocation
+buddies.deallocate(idx1, 1);
+```"#
+    )]
     pub fn deallocate(&self, idx: usize, size: usize) {
+        self.raw.deallocate_with_size(idx, size);
+    }
+
+    /// deallocate a buddy with a given size and return the free block it was merged into
+    ///
+    /// the block is returned as `(idx, size)`, it was free when the deallocation finished but may
+    /// have been allocated again by another thread since then.
+    ///
+    /// with the `quarantine` feature the buddy is held back and the returned block is the one the
+    /// buddy released in its place was merged into, or `(idx, 0)` if no buddy was released. see
+    /// [Buddies::flush_quarantine](Buddies::flush_quarantine)
+    ///
+    /// see [Buddies::deallocate](Buddies::deallocate)
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 1, None);
+    /// let idx1 = buddies.allocate(4, 1).unwrap();
+    /// let idx2 = buddies.allocate(4, 1).unwrap();
+    /// let idx3 = buddies.allocate(8, 1).unwrap();
+    /// # #[cfg(not(feature = "quarantine"))] {
+    /// assert_eq!(buddies.deallocate_report(idx1, 4), (idx1, 4));
+    /// // the second half completes the block of 8
+    /// assert_eq!(buddies.deallocate_report(idx2, 4), (0, 8));
+    /// // which in turn completes the whole capacity
+    /// assert_eq!(buddies.deallocate_report(idx3, 8), (0, 16));
+    /// # }
+    /// ```
+    #[cfg_attr(
+        feature = "quarantine",
+        doc = r#"```
+use buddy_allocator::Buddies;
+
+let buddies = Buddies::new(6, 1, None);
+let idxs: Vec<_> = (0..17).map(|_| buddies.allocate(1, 1).unwrap()).collect();
+for &idx in &idxs[..16] {
+    assert_eq!(buddies.deallocate_report(idx, 1), (idx, 0));
+}
+// the quarantine is full, the first buddy is released in place of the last one
+assert_eq!(buddies.deallocate_report(idxs[16], 1), (idxs[0], 1));
+buddies.flush_quarantine();
+assert!(buddies.is_unused());
+```"#
+    )]
+    pub fn deallocate_report(&self, idx: usize, size: usize) -> (usize, usize) {
         self.raw.deallocate_with_size(idx, size)
     }
 
+    /// deallocate a buddy with a given order
+    ///
+    /// see [Buddies::deallocate](Buddies::deallocate)
+    /// # Panics
+    /// panics if:
+    /// - `order` is not less than `max_order`
+    /// - there is no buddy with that order allocated at that index
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 4, None);
+    /// let idx = buddies.allocate_order(1, 1).unwrap();
+    /// assert_eq!(buddies.allocation_size_at(idx), Some(8));
+    /// buddies.deallocate_order(idx, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert!(buddies.is_unused());
+    /// ```
+    pub fn deallocate_order(&self, idx: usize, order: usize) {
+        self.raw.deallocate_order(idx, order);
+    }
+
+    /// get the size of the buddy allocated at a given index
+    ///
+    /// returns `None` if no buddy starts at that index. this checks one block per order, so it
+    /// takes `O(max_order)` steps.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 4, None);
+    /// let idx1 = buddies.allocate(3, 1).unwrap();
+    /// let idx2 = buddies.allocate(8, 1).unwrap();
+    /// let idx3 = buddies.allocate(8, 1).unwrap();
+    /// assert_eq!(buddies.allocation_size_at(idx1), Some(4));
+    /// assert_eq!(buddies.allocation_size_at(idx2), Some(8));
+    /// assert_eq!(buddies.allocation_size_at(idx3), Some(8));
+    /// assert_eq!(buddies.allocation_size_at(idx1 + 4), None);
+    /// assert_eq!(buddies.allocation_size_at(idx2 + 4), None);
+    /// assert_eq!(buddies.allocation_size_at(1), None);
+    /// assert_eq!(buddies.allocation_size_at(64), None);
+    ///
+    /// buddies.shrink(idx2, 8, 4);
+    /// assert_eq!(buddies.allocation_size_at(idx2), Some(4));
+    /// buddies.deallocate(idx1, 3);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocation_size_at(idx1), None);
+    /// ```
+    pub fn allocation_size_at(&self, idx: usize) -> Option<usize> {
+        self.raw.allocation_size_at(idx)
+    }
+
+    /// deallocate the buddy allocated at a given index
+    ///
+    /// see [Buddies::allocation_size_at](Buddies::allocation_size_at)
+    /// # Panics
+    /// panics if:
+    /// - there is no buddy allocated at that index
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 1, None);
+    /// let idx1 = buddies.allocate(1, 1).unwrap();
+    /// let idx2 = buddies.allocate(4, 1).unwrap();
+    /// buddies.deallocate_by_idx(idx2);
+    /// buddies.deallocate_by_idx(idx1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert!(buddies.is_unused());
+    /// ```
+    pub fn deallocate_by_idx(&self, idx: usize) {
+        let size = self
+            .allocation_size_at(idx)
+            .unwrap_or_else(|| panic!("{} is not allocated", idx));
+        self.deallocate(idx, size)
+    }
+
+    /// release all buddies held back by the quarantine
+    ///
+    /// with the `quarantine` feature deallocated buddies aren't reused right away, they stay
+    /// allocated until 16 more buddies were deallocated or until this is called. deallocating a
+    /// quarantined buddy again panics. quarantined buddies still count as allocations, so
+    /// [Buddies::is_unused](Buddies::is_unused) only returns true after flushing.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// let idx = buddies.allocate(2, 1).unwrap();
+    /// buddies.deallocate(idx, 2);
+    /// assert_eq!(buddies.allocation_size_at(idx), Some(2));
+    /// assert_eq!(buddies.allocate(4, 1), None);
+    /// assert!(!buddies.is_currently_unused());
+    ///
+    /// buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocation_size_at(idx), None);
+    /// assert!(buddies.is_currently_unused());
+    /// assert_eq!(buddies.allocate(4, 1), Some(0));
+    /// ```
+    ///
+    /// ```should_panic
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// let idx = buddies.allocate(4, 1).unwrap();
+    /// buddies.deallocate(idx, 4);
+    /// buddies.deallocate(idx, 4);
+    /// ```
+    #[cfg(feature = "quarantine")]
+    pub fn flush_quarantine(&self) {
+        self.raw.flush_quarantine()
+    }
+
+    /// split an allocated buddy into two allocated halves
+    ///
+    /// returns the indices of the halves, they can be deallocated, shrunk and grown
+    /// independently. this counts as one more allocation.
+    /// # Panics
+    /// panics if:
+    /// - `size` isn't the size of a buddy, see [Buddies::is_exact_size](Buddies::is_exact_size)
+    /// - `size` is the size of the smallest buddies
+    /// - there is no buddy with that size allocated at that index
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::ReallocPlacement;
+    /// use buddy_allocator::Buddies;
+    /// use std::panic::{catch_unwind, AssertUnwindSafe};
+    ///
+    /// let buddies = Buddies::new(5, 2, None);
+    /// let idx = buddies.allocate(16, 1).unwrap();
+    /// assert_eq!(buddies.split(idx, 16), (0, 8));
+    /// assert_eq!(buddies.num_allocations(), 2);
+    /// assert_eq!(buddies.allocation_size_at(8), Some(8));
+    ///
+    /// // the halves have separate lifetimes
+    /// buddies.deallocate(8, 8);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocation_size_at(0), Some(8));
+    /// assert_eq!(buddies.grow(0, 8, 16, ReallocPlacement::InPlace), Some(0));
+    ///
+    /// assert!(catch_unwind(AssertUnwindSafe(|| buddies.split(0, 12))).is_err());
+    /// assert!(catch_unwind(AssertUnwindSafe(|| buddies.split(16, 16))).is_err());
+    /// let idx = buddies.allocate(2, 1).unwrap();
+    /// assert!(catch_unwind(AssertUnwindSafe(|| buddies.split(idx, 2))).is_err());
+    /// assert_eq!(buddies.num_allocations(), 2);
+    /// assert_eq!(buddies.verify_integrity(), Ok(()));
+    /// ```
+    pub fn split(&self, idx: usize, size: usize) -> (usize, usize) {
+        self.raw.split_with_size(idx, size)
+    }
+
+    /// merge two allocated buddies into one allocation
+    ///
+    /// this is the inverse of [Buddies::split](Buddies::split), the buddies at `left_idx` and
+    /// `right_idx` both have to be allocated with `size`. returns the index of the merged
+    /// allocation, which counts as one allocation less.
+    /// ```
+    /// use buddy_allocator::{Buddies, MergeError};
+    ///
+    /// let buddies = Buddies::with_capacity(12, 1);
+    /// assert!(buddies.allocate_at(4, 0));
+    /// assert!(buddies.allocate_at(4, 4));
+    /// assert!(buddies.allocate_at(2, 8));
+    ///
+    /// assert_eq!(buddies.merge(4, 8, 4), Err(MergeError::NotBuddies));
+    /// assert_eq!(buddies.merge(0, 4, 3), Err(MergeError::NotBuddies));
+    /// assert_eq!(buddies.merge(8, 10, 2), Err(MergeError::NotAllocated { idx: 10 }));
+    /// assert_eq!(
+    ///     buddies.merge(8, 10, 4),
+    ///     Err(MergeError::NotBuddies)
+    /// );
+    /// assert!(buddies.allocate_at(1, 10));
+    /// assert_eq!(
+    ///     buddies.merge(8, 10, 2),
+    ///     Err(MergeError::SizeMismatch { idx: 10, size: 1 })
+    /// );
+    ///
+    /// assert_eq!(buddies.merge(0, 4, 4), Ok(0));
+    /// assert_eq!(buddies.num_allocations(), 3);
+    /// assert_eq!(buddies.allocation_size_at(0), Some(8));
+    /// buddies.deallocate(0, 8);
+    /// buddies.deallocate(8, 2);
+    /// buddies.deallocate(10, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert!(buddies.is_unused());
+    /// ```
+    pub fn merge(
+        &self,
+        left_idx: usize,
+        right_idx: usize,
+        size: usize,
+    ) -> Result<usize, MergeError> {
+        self.raw.merge_with_size(left_idx, right_idx, size)
+    }
+
     /// shrink a buddy
+    ///
+    /// unlike `realloc` shrinking to size 0 doesn't free the buddy, use
+    /// [Buddies::deallocate](Buddies::deallocate) for that.
     /// # Panics
     /// panics if:
     /// - there is no buddy with that size allocated at that index
     /// - `new_size` is greater that `old_size`
+    /// - `new_size` is 0
     /// ```
     /// use buddy_allocator::Buddies;
     ///
@@ -195,13 +1864,102 @@ impl<A: AllocRef> Buddies<A> {
     /// let idx = buddies.allocate(3, 1).unwrap();
     /// buddies.shrink(idx, 3, 2);
     /// buddies.shrink(idx, 2, 1);
-    /// buddies.shrink(idx, 1, 0);
+    /// buddies.deallocate(idx, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.num_allocations(), 0);
+    /// ```
+    ///
+    /// shrinking to 0 panics and leaves the buddy allocated
+    /// ```
+    /// use buddy_allocator::Buddies;
+    /// use std::panic::{catch_unwind, AssertUnwindSafe};
+    ///
+    /// let buddies = Buddies::new(3, 1, None);
+    /// let idx = buddies.allocate(2, 1).unwrap();
+    /// assert!(catch_unwind(AssertUnwindSafe(|| buddies.shrink(idx, 2, 0))).is_err());
+    /// assert_eq!(buddies.allocation_size_at(idx), Some(2));
+    /// buddies.deallocate(idx, 2);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.num_allocations(), 0);
     /// ```
+    ///
+    /// with the `debug-integrity` feature the buddy is checked like in
+    /// [Buddies::deallocate](Buddies::deallocate)
+    #[cfg_attr(
+        feature = "debug-integrity",
+        doc = r#"```should_panic
+use buddy_allocator::Buddies;
+
+let buddies = Buddies::new(5, 1, None);
+let idx1 = buddies.allocate(1, 1).unwrap();
+let idx2 = buddies.allocate(1, 1).unwrap();
+buddies.deallocate(idx1, 1);
+buddies.deallocate(idx2, 1);
+# #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+buddies.shrink(idx1, 2, 1);
+```"#
+    )]
     pub fn shrink(&self, idx: usize, old_size: usize, new_size: usize) {
         self.raw.shrink_with_size(idx, old_size, new_size)
     }
 
+    /// shrink a buddy, moving it to a free buddy of the new size if the placement allows it
+    ///
+    /// with `MayMove` the allocation is moved to a free buddy of the new size aligned to `align`
+    /// if there is one, so the old buddy is released entirely and can merge again. bigger free
+    /// buddies aren't split for this. the caller has to move the data before anyone else can
+    /// allocate the old buddy. otherwise the buddy is shrunk in place like with
+    /// [Buddies::shrink](Buddies::shrink). returns the new index.
+    /// # Panics
+    /// panics if:
+    /// - there is no buddy with that size allocated at that index
+    /// - `new_size` is 0
+    /// - `align` is not a power of two or too big
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::ReallocPlacement;
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(5, 1, None);
+    /// assert!(buddies.allocate_at(8, 0));
+    /// assert!(buddies.allocate_at(1, 8));
+    /// // 9 is free
+    /// assert_eq!(buddies.shrink_with_placement(0, 8, 1, 1, ReallocPlacement::MayMove), 9);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocation_size_at(0), None);
+    /// assert_eq!(buddies.allocate(8, 1), Some(0));
+    ///
+    /// // 10 is a free buddy of 2 but it isn't aligned to 4, bigger ones aren't split
+    /// assert_eq!(buddies.shrink_with_placement(0, 8, 2, 4, ReallocPlacement::MayMove), 0);
+    /// assert_eq!(buddies.allocation_size_at(0), Some(2));
+    ///
+    /// let idx = buddies.allocate(4, 1).unwrap();
+    /// assert_eq!(buddies.shrink_with_placement(idx, 4, 1, 1, ReallocPlacement::InPlace), idx);
+    /// ```
+    pub fn shrink_with_placement(
+        &self,
+        idx: usize,
+        old_size: usize,
+        new_size: usize,
+        align: usize,
+        placement: ReallocPlacement,
+    ) -> usize {
+        self.raw
+            .shrink_or_move_with_size(idx, old_size, new_size, align, placement)
+    }
+
+    /// allocate a free buddy of a size without splitting a bigger one
+    pub(crate) fn allocate_without_splitting(&self, size: usize, align: usize) -> Option<usize> {
+        self.raw
+            .allocate_without_splitting(self.raw.order_for_size(size), align)
+    }
+
     /// grow a buddy
+    ///
+    /// the buddy is grown by merging it with its buddies. if that fails and the placement is
+    /// `MayMove`, the allocation moves to a new block which keeps the alignment of the old index
+    /// and the old block is released, so the caller has to move the data before anyone else can
+    /// reuse the old block.
     /// # Panics
     /// panics if:
     /// - there is no buddy with that size allocated at that index
@@ -217,13 +1975,59 @@ impl<A: AllocRef> Buddies<A> {
     /// let idx = buddies.grow(idx, 0, 1, ReallocPlacement::InPlace).unwrap();
     /// let idx = buddies.grow(idx, 1, 2, ReallocPlacement::MayMove).unwrap();
     /// buddies.grow(idx, 2, 3, ReallocPlacement::InPlace).unwrap();
+    ///
+    /// // the buddy of the block at 4 is taken, so growing it has to move it
+    /// let buddies = Buddies::new(4, 1, None);
+    /// assert!(buddies.allocate_at(2, 4));
+    /// assert!(buddies.allocate_at(2, 6));
+    /// assert_eq!(buddies.grow(4, 2, 4, ReallocPlacement::InPlace), None);
+    /// assert_eq!(buddies.grow(4, 2, 4, ReallocPlacement::MayMove), Some(0));
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocation_size_at(0), Some(4));
+    /// assert_eq!(buddies.allocation_size_at(4), None);
+    /// assert_eq!(buddies.num_allocations(), 2);
+    ///
+    /// // the block at 0 can't move without losing its alignment
+    /// assert_eq!(buddies.grow(0, 4, 8, ReallocPlacement::MayMove), None);
+    /// assert_eq!(buddies.allocation_size_at(0), Some(4));
     /// ```
+    ///
+    /// with the `debug-integrity` feature the buddy is checked like in
+    /// [Buddies::deallocate](Buddies::deallocate)
+    #[cfg_attr(
+        feature = "debug-integrity",
+        doc = r#"```should_panic
+#![feature(allocator_api)]
+use alloc_wg::alloc::ReallocPlacement;
+use buddy_allocator::Buddies;
+
+let buddies = Buddies::new(5, 1, None);
+let idx1 = buddies.allocate(1, 1).unwrap();
+let idx2 = buddies.allocate(1, 1).unwrap();
+buddies.deallocate(idx1, 1);
+buddies.deallocate(idx2, 1);
+# #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+buddies.grow(idx1, 1, 4, ReallocPlacement::InPlace);
+```"#
+    )]
     pub fn grow(
         &self,
         idx: usize,
         old_size: usize,
         new_size: usize,
         placement: ReallocPlacement,
+    ) -> Option<usize> {
+        self.raw
+            .grow_or_move_with_size(idx, old_size, new_size, placement)
+    }
+
+    /// grow a buddy without moving it to a new block
+    pub(crate) fn grow_by_merging(
+        &self,
+        idx: usize,
+        old_size: usize,
+        new_size: usize,
+        placement: ReallocPlacement,
     ) -> Option<usize> {
         self.raw.grow_with_size(idx, old_size, new_size, placement)
     }
@@ -1,260 +1,2605 @@
+mod compaction;
+#[cfg(feature = "quarantine")]
+mod quarantine;
+#[cfg(feature = "serde")]
+mod serialize;
+mod state;
+
+pub use compaction::{CompactionMove, CompactionPlan};
+pub use state::{ExportError, ImportError};
+
+#[cfg(feature = "static")]
+use crate::sync::{AtomicBool, AtomicIndex, AtomicIsize, AtomicUsize};
+use crate::{
+    sync::{spin_loop, widen, Atomic, AtomicInt, Atomics, Backend, CachePadded, Index, Ordering},
+    DecommitHandler,
+};
 use alloc_wg::{
     alloc::{AllocRef, ReallocPlacement},
     vec::Vec,
 };
-use core::{
-    ops::Index,
-    sync::atomic::{AtomicBool, AtomicIsize, Ordering},
-};
+use core::{fmt, mem};
 
-pub struct RawBuddies<A: AllocRef> {
-    allocations: AtomicIsize,
-    blocks: Vec<AtomicBool, A>,
+pub struct RawBuddies<S, B: Backend = Atomics> {
+    allocations: CachePadded<B::Isize>,
+    /// the sum of the real sizes of all allocations
+    #[cfg(feature = "stats")]
+    used: CachePadded<B::Usize>,
+    /// the highest value of `used` since creation or the last reset
+    #[cfg(feature = "stats")]
+    peak: CachePadded<B::Usize>,
+    /// the number of compare exchanges on free bits that had to be retried
+    #[cfg(feature = "stats")]
+    claim_retries: CachePadded<B::Usize>,
+    /// the number of splits, merges and grows in progress, see [RawBuddies::begin_reshape]
+    reshaping: CachePadded<B::Usize>,
+    /// the number of finished splits, merges and grows
+    reshaped: CachePadded<B::Usize>,
+    /// the number of free blocks per order
+    ///
+    /// a counter is incremented before a block is marked as free and decremented after it was
+    /// claimed, so it's never lower than the number of free blocks. if it's zero, scanning the
+    /// order can be skipped.
+    free: CachePadded<[B::Index; WORD_BITS]>,
+    /// the index where scans start per order
+    ///
+    /// this is only a hint, but without concurrent modifications all blocks below it are taken.
+    /// it's moved back when a block below it becomes free and moved forward by scans.
+    search_start: [B::Index; WORD_BITS],
+    /// see [ScanStart::Striding]
+    striding: B::Bool,
+    /// the number of scans started in striding mode
+    scans: B::Usize,
+    /// see [Placement::Clustered]
+    clustered: B::Bool,
+    /// deallocated blocks that aren't released yet
+    #[cfg(feature = "quarantine")]
+    quarantine: quarantine::Quarantine<B>,
+    /// see [RawBuddies::set_decommit_handler]
+    decommit: Option<(DecommitHandler, usize)>,
+    /// see [row_offsets]
+    rows: [usize; WORD_BITS],
+    blocks: S,
     max_order: usize,
     base_shift: usize,
     max_idx: usize,
+    base: usize,
+}
+
+const WORD_BITS: usize = mem::size_of::<usize>() * 8;
+
+// only used as initializer
+#[cfg(feature = "static")]
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_WORD: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "static")]
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_INDEX: AtomicIndex = AtomicIndex::new(0);
+
+/// one of the two bits every block has
+#[derive(Clone, Copy)]
+enum Bit {
+    /// the block is free
+    Available,
+    /// the block was handed out as a single allocation
+    Allocated,
+}
+
+/// what [RawBuddies::scan] does with the free block it finds
+#[derive(Clone, Copy)]
+enum Claim {
+    /// return it without claiming it
+    No,
+    /// claim it for an allocation
+    Allocation,
+    /// claim it to split it, see [RawBuddies::begin_reshape]
+    Split,
+}
+
+/// storage for the bits of a buddy tree
+///
+/// the words hold two bitmaps with one bit per block, first the free bits and then the allocated
+/// bits. bit `i % WORD_BITS` of word `i / WORD_BITS` in a bitmap belongs to the block at position
+/// `i` in the storage. only the blocks starting below the capacity are stored, see
+/// [row_offsets].
+///
+/// unless the `linear-scan` feature is enabled they are followed by the summary, it has one bit
+/// per word of free bits which is set if the word may be non-zero, see [summary_words].
+pub trait Storage<W> {
+    fn as_words(&self) -> &[W];
+}
+
+impl<W, A: AllocRef> Storage<W> for Vec<W, A> {
+    fn as_words(&self) -> &[W] {
+        self
+    }
+}
+
+impl<W, const N: usize> Storage<W> for [W; N] {
+    fn as_words(&self) -> &[W] {
+        self
+    }
+}
+
+/// the number of blocks in a tree with `max_order` orders
+pub const fn blocks(max_order: usize) -> usize {
+    (1 << max_order) - 1
+}
+
+/// the number of words storing the bits of a tree with `max_order` orders
+///
+/// this is enough for any capacity
+#[cfg(feature = "static")]
+pub const fn words(max_order: usize) -> usize {
+    storage_words(blocks(max_order))
+}
+
+/// the number of words storing the bits of `blocks` blocks
+const fn storage_words(blocks: usize) -> usize {
+    let free_words = blocks.div_ceil(WORD_BITS);
+    2 * free_words + summary_words(free_words)
+}
+
+/// whether the summary is stored
+const SUMMARY: bool = !cfg!(feature = "linear-scan");
+
+/// the number of words of the summary for `free_words` words of free bits
+///
+/// a scan skips the words whose summary bit isn't set. a bit may be set although its word is
+/// zero, but it's never clear while its word has free bits: setting a free bit is followed by
+/// setting the summary bit and clearing the summary bit after the last free bit was claimed is
+/// followed by checking the word again. these accesses are sequentially consistent, so either
+/// the check sees the new free bit or the store setting it is followed by setting the summary
+/// bit again.
+const fn summary_words(free_words: usize) -> usize {
+    if !SUMMARY {
+        0
+    } else {
+        free_words.div_ceil(WORD_BITS)
+    }
+}
+
+/// the position of the first block of each order in the storage, the entry after the last order
+/// is the number of stored blocks
+///
+/// only the blocks starting below `max_blocks` are stored, the blocks after them can never be
+/// used.
+const fn row_offsets(max_order: usize, max_blocks: usize) -> [usize; WORD_BITS] {
+    let mut offsets = [0; WORD_BITS];
+    let mut order = 0;
+    while order < max_order {
+        let block_size = calculate_block_size(max_order, order);
+        offsets[order + 1] = offsets[order] + max_blocks.div_ceil(block_size);
+        order += 1;
+    }
+    offsets
+}
+
+/// the fraction of the golden ratio scaled to a word, multiples of it modulo any number are
+/// evenly distributed
+const GOLDEN_RATIO: usize = (0x9e37_79b9_7f4a_7c15_u64 >> (64 - WORD_BITS)) as usize;
+
+/// mark a bit as not set, returns the remaining bits of the word or `None` if it wasn't set
+///
+/// `retries` is incremented for every failed compare exchange
+fn claim_bit(
+    word: &impl AtomicInt<usize>,
+    mask: usize,
+    success: Ordering,
+    retries: &mut usize,
+) -> Option<usize> {
+    let mut current = word.load(Ordering::Relaxed);
+    loop {
+        if current & mask == 0 {
+            return None;
+        }
+        match word.compare_exchange_weak(current, current & !mask, success, Ordering::Relaxed) {
+            Ok(_) => return Some(current & !mask),
+            // another bit in the word changed or the failure was spurious
+            Err(actual) => {
+                current = actual;
+                *retries += 1;
+            }
+        }
+    }
+}
+
+/// where scans for free blocks start, see [Buddies::set_scan_start](crate::Buddies::set_scan_start)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanStart {
+    /// start at the lowest block that may be free
+    ///
+    /// the placement is deterministic, the lowest free block is always used. concurrent
+    /// allocations start at the same block though, so they contend for the same words.
+    Lowest,
+    /// start every scan at a different block, consecutive scans are spread over the whole row
+    ///
+    /// concurrent allocations only contend for the same words when most blocks are taken, but
+    /// the placement isn't deterministic anymore and the memory gets more fragmented. scans
+    /// often start after the free blocks and have to wrap around, so without contention this is
+    /// slower.
+    Striding,
+}
+
+/// which free block an allocation is taken from, see
+/// [Buddies::set_placement](crate::Buddies::set_placement)
+/// ```
+/// use buddy_allocator::{Buddies, Placement};
+///
+/// // leaves a free block of 8 at 0 and a free block of 2 at 12
+/// let buddies = |placement| {
+///     let buddies = Buddies::new(5, 1, None).with_placement(placement);
+///     assert!(buddies.allocate_at(4, 8));
+///     assert!(buddies.allocate_at(2, 14));
+///     buddies
+/// };
+///
+/// // the smallest free ancestor is split, then the free block of the requested size is used
+/// let smallest = buddies(Placement::SmallestBlock);
+/// assert_eq!(smallest.allocate(1, 1), Some(12));
+/// assert_eq!(smallest.allocate(1, 1), Some(13));
+/// assert_eq!(smallest.allocate(1, 1), Some(0));
+///
+/// // the lowest free block is split
+/// let clustered = buddies(Placement::Clustered);
+/// assert_eq!(clustered.allocate(1, 1), Some(0));
+/// assert_eq!(clustered.allocate(1, 1), Some(1));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// take the lowest free block of the smallest order that fits
+    ///
+    /// a bigger block is only split if there's no free block of the requested order, no matter
+    /// where they are. this is best fit: the requested order is searched completely first and
+    /// then the smallest ancestor that's free is split.
+    SmallestBlock,
+    /// take the lowest free block that fits, splitting it if it's bigger than necessary
+    ///
+    /// this is first fit ordered by index.
+    ///
+    /// the allocations are packed towards the start, so the free blocks at the end stay intact
+    /// and the small blocks freed there can merge again. searching costs a scan of every order
+    /// bigger than the requested one for every allocation though.
+    Clustered,
+}
+
+/// the state of the buddies when an allocation failed, returned by
+/// [Buddies::allocate_or_explain](crate::Buddies::allocate_or_explain)
+///
+/// if there are enough free bytes for the allocation but no free block is big enough, the free
+/// memory is too fragmented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocFailure {
+    /// the sum of the sizes of the free blocks
+    pub free_bytes: usize,
+    /// the size of the biggest free block, 0 if nothing is free
+    pub largest_free_block: usize,
+}
+
+impl fmt::Display for AllocFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "allocation failed with {} free bytes in blocks of up to {} bytes",
+            self.free_bytes, self.largest_free_block
+        )
+    }
+}
+
+/// error returned by [Buddies::merge](crate::Buddies::merge)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeError {
+    /// the indices aren't the two halves of a block twice the size
+    NotBuddies,
+    /// there is no allocation at the index
+    NotAllocated { idx: usize },
+    /// the allocation at the index has a different size
+    SizeMismatch { idx: usize, size: usize },
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            MergeError::NotBuddies => write!(f, "the allocations aren't buddies"),
+            MergeError::NotAllocated { idx } => write!(f, "{} is not allocated", idx),
+            MergeError::SizeMismatch { idx, size } => {
+                write!(f, "{} is allocated with size {}", idx, size)
+            }
+        }
+    }
+}
+
+/// a violated invariant of the buddy tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// the block is marked as free and as allocated at the same time
+    FreeAndAllocated { order: usize, idx: usize },
+    /// the block extends past the capacity
+    OutOfBounds { order: usize, idx: usize },
+    /// the block is free or allocated while an ancestor is free or allocated too
+    Overlap { order: usize, idx: usize },
+    /// some memory is neither free nor allocated
+    Lost { free: usize, allocated: usize },
+    /// the allocations counter doesn't match the number of allocated blocks
+    Allocations { counter: isize, allocated: usize },
+    /// the free counter of the order doesn't match the number of free blocks
+    FreeCount {
+        order: usize,
+        counter: usize,
+        free: usize,
+    },
+    /// the block is free but the summary says its word has no free blocks
+    Unsummarized { order: usize, idx: usize },
+    /// the used bytes don't match the size of the allocated blocks
+    Used { counter: usize, allocated: usize },
 }
 
-fn calculate_block_size(max_order: usize, order: usize) -> usize {
+/// error returned by [Buddies::try_new_in](crate::Buddies::try_new_in)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryNewError {
+    /// the parameters are invalid
+    InvalidParameters(&'static str),
+    /// allocating the blocks failed
+    AllocError,
+    /// there are more blocks than the counters can hold, only returned with the `compact`
+    /// feature
+    TooManyBlocks { blocks: usize },
+}
+
+impl fmt::Display for TryNewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TryNewError::InvalidParameters(msg) => write!(f, "invalid parameters: {}", msg),
+            TryNewError::AllocError => write!(f, "failed to allocate the blocks"),
+            TryNewError::TooManyBlocks { blocks } => {
+                write!(f, "{} blocks don't fit into the compact counters", blocks)
+            }
+        }
+    }
+}
+
+/// error returned by [Buddies::with_used_ranges](crate::Buddies::with_used_ranges)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// creating the buddies failed
+    New(TryNewError),
+    /// the range extends past the capacity
+    OutOfBounds { idx: usize, size: usize },
+    /// the range overlaps with another range
+    Overlap { idx: usize, size: usize },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BuildError::New(err) => write!(f, "{}", err),
+            BuildError::OutOfBounds { idx, size } => {
+                write!(f, "{} with size {} is out of bounds", idx, size)
+            }
+            BuildError::Overlap { idx, size } => {
+                write!(f, "{} with size {} overlaps with another range", idx, size)
+            }
+        }
+    }
+}
+
+impl From<TryNewError> for BuildError {
+    fn from(err: TryNewError) -> Self {
+        BuildError::New(err)
+    }
+}
+
+impl fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            IntegrityError::FreeAndAllocated { order, idx } => write!(
+                f,
+                "{} at order {} is marked as free and allocated",
+                idx, order
+            ),
+            IntegrityError::OutOfBounds { order, idx } => {
+                write!(f, "{} at order {} is out of bounds", idx, order)
+            }
+            IntegrityError::Overlap { order, idx } => write!(
+                f,
+                "{} at order {} overlaps with an ancestor that is free or allocated",
+                idx, order
+            ),
+            IntegrityError::Lost { free, allocated } => write!(
+                f,
+                "{} free and {} allocated units don't add up to the capacity",
+                free, allocated
+            ),
+            IntegrityError::Allocations { counter, allocated } => write!(
+                f,
+                "allocations counter is {} but {} blocks are allocated",
+                counter, allocated
+            ),
+            IntegrityError::FreeCount {
+                order,
+                counter,
+                free,
+            } => write!(
+                f,
+                "free counter of order {} is {} but {} blocks are free",
+                order, counter, free
+            ),
+            IntegrityError::Unsummarized { order, idx } => write!(
+                f,
+                "{} at order {} is free but missing from the summary",
+                idx, order
+            ),
+            IntegrityError::Used { counter, allocated } => write!(
+                f,
+                "used bytes are {} but {} bytes are allocated",
+                counter, allocated
+            ),
+        }
+    }
+}
+
+const fn calculate_block_size(max_order: usize, order: usize) -> usize {
     let order_diff = max_order - order - 1;
     1 << order_diff
 }
 
+/// the order of the smallest block with at least `size`
+///
+/// the size is rounded up to whole units of `1 << base_shift` first, a size of zero needs one
+/// unit like any other size up to a unit.
 fn calculate_order_for_size(max_order: usize, base_shift: usize, size: usize) -> usize {
-    let size = size.next_power_of_two();
-    let size = size >> base_shift;
-    let size = size.max(1);
-    let shift = size.trailing_zeros() as usize;
+    let units = size.div_ceil(1 << base_shift).max(1);
+    let shift = units.next_power_of_two().trailing_zeros() as usize;
     max_order - shift - 1
 }
 
-impl<A: AllocRef> RawBuddies<A> {
+/// the smallest max order whose capacity is at least `capacity`, `None` if the tree wouldn't
+/// fit into the index space
+fn max_order_for_capacity(capacity: usize, multiplier: usize) -> Option<usize> {
+    let blocks = capacity.div_ceil(multiplier).checked_next_power_of_two()?;
+    let max_order = blocks.trailing_zeros() as usize + 1;
+    // the capacity of the whole tree must be an index too
+    if max_order >= WORD_BITS || blocks.checked_mul(multiplier).is_none() {
+        return None;
+    }
+    Some(max_order)
+}
+
+/// allocate `len` words with all bits cleared
+///
+/// the memory is zeroed by the allocator instead of initializing the words one by one, this
+/// makes creating huge trees cheap.
+#[cfg(not(loom))]
+fn zeroed_words<B: Backend, A: AllocRef>(
+    len: usize,
+    mut a: A,
+) -> Result<Vec<B::Usize, A>, TryNewError> {
+    use alloc_wg::alloc::{AllocInit, Layout};
+
+    let layout = Layout::array::<B::Usize>(len)
+        .map_err(|_| TryNewError::InvalidParameters("capacity overflows"))?;
+    let memory = a
+        .alloc(layout, AllocInit::Zeroed)
+        .map_err(|_| TryNewError::AllocError)?;
+    // zeroed memory is a valid word with no bits set, see [Backend]
+    Ok(unsafe { Vec::from_raw_parts_in(memory.ptr.cast().as_ptr(), len, len, a) })
+}
+
+/// loom's atomics can't be zeroed, so they are created one by one
+#[cfg(loom)]
+fn zeroed_words<B: Backend, A: AllocRef>(
+    len: usize,
+    a: A,
+) -> Result<Vec<B::Usize, A>, TryNewError> {
+    let mut words = Vec::try_with_capacity_in(len, a).map_err(|_| TryNewError::AllocError)?;
+    for _ in 0..len {
+        words.push(B::Usize::new(0));
+    }
+    Ok(words)
+}
+
+/// the position of the block at `idx` in `order` in the storage
+const fn block_index(
+    rows: &[usize; WORD_BITS],
+    max_order: usize,
+    order: usize,
+    idx: usize,
+) -> usize {
+    rows[order] + (idx >> (max_order - order - 1))
+}
+
+impl<A: AllocRef, B: Backend> RawBuddies<Vec<B::Usize, A>, B> {
     pub fn new_in(max_order: usize, multiplier: usize, max_idx: Option<usize>, a: A) -> Self {
-        assert_ne!(max_order, 0, "max order must be not be zero");
-        assert!(
-            multiplier.is_power_of_two(),
-            "multiplier must be a power of two"
-        );
+        Self::try_new_in(max_order, multiplier, max_idx, a).unwrap_or_else(|err| panic!("{}", err))
+    }
 
-        let max_blocks = (1 << max_order) - 1;
-        let mut blocks = Vec::with_capacity_in(max_blocks, a);
-        for _ in 0..max_blocks {
-            blocks.push(AtomicBool::new(false));
+    pub fn try_new_in(
+        max_order: usize,
+        multiplier: usize,
+        max_idx: Option<usize>,
+        a: A,
+    ) -> Result<Self, TryNewError> {
+        if max_order == 0 || max_order >= WORD_BITS {
+            return Err(TryNewError::InvalidParameters("invalid max_order"));
+        }
+        if !multiplier.is_power_of_two() {
+            return Err(TryNewError::InvalidParameters(
+                "multiplier must be a power of two",
+            ));
         }
 
         // convert multiplier to shifts
         let base_shift = multiplier.trailing_zeros() as usize;
-        let default_max_idx = calculate_block_size(max_order, 0) << base_shift;
+        let default_max_idx = calculate_block_size(max_order, 0)
+            .checked_mul(multiplier)
+            .ok_or(TryNewError::InvalidParameters("capacity overflows"))?;
 
         // check bounds on max_idx
         let max_idx = if let Some(max_idx) = max_idx {
-            assert_eq!(
-                max_idx % multiplier,
-                0,
-                "max_idx {} is not a multiple of multiplier {}",
-                max_idx,
-                multiplier
-            );
-            assert!(
-                max_idx <= default_max_idx,
-                "max_idx {} is too big (expected less than {})",
-                max_idx,
-                default_max_idx
-            );
-            assert!(
-                max_idx > default_max_idx / 2,
-                "max_idx {} is too small (expected more than {})",
-                max_idx,
-                default_max_idx / 2
-            );
+            if max_idx % multiplier != 0 {
+                return Err(TryNewError::InvalidParameters(
+                    "max_idx is not a multiple of multiplier",
+                ));
+            }
+            if max_idx > default_max_idx {
+                return Err(TryNewError::InvalidParameters("max_idx is too big"));
+            }
+            if max_idx <= default_max_idx / 2 {
+                return Err(TryNewError::InvalidParameters("max_idx is too small"));
+            }
             max_idx
         } else {
             default_max_idx
         };
+        #[cfg(feature = "compact")]
+        if max_idx >> base_shift > Index::MAX as usize {
+            return Err(TryNewError::TooManyBlocks {
+                blocks: max_idx >> base_shift,
+            });
+        }
+
+        let rows = row_offsets(max_order, max_idx >> base_shift);
+        let blocks = zeroed_words::<B, A>(storage_words(rows[max_order]), a)?;
 
         let buddies = RawBuddies {
-            allocations: AtomicIsize::new(0),
+            allocations: CachePadded(B::Isize::new(0)),
+            #[cfg(feature = "stats")]
+            used: CachePadded(B::Usize::new(0)),
+            #[cfg(feature = "stats")]
+            peak: CachePadded(B::Usize::new(0)),
+            #[cfg(feature = "stats")]
+            claim_retries: CachePadded(B::Usize::new(0)),
+            reshaping: CachePadded(B::Usize::new(0)),
+            reshaped: CachePadded(B::Usize::new(0)),
+            free: CachePadded(core::array::from_fn(|_| B::Index::new(0))),
+            search_start: core::array::from_fn(|_| B::Index::new(0)),
+            striding: B::Bool::new(false),
+            scans: B::Usize::new(0),
+            clustered: B::Bool::new(false),
+            #[cfg(feature = "quarantine")]
+            quarantine: quarantine::Quarantine {
+                next: B::Usize::new(0),
+                slots: core::array::from_fn(|_| B::Usize::new(0)),
+            },
+            decommit: None,
+            rows,
             blocks,
             max_order,
             base_shift,
             max_idx,
+            base: 0,
+        };
+
+        let mut idx = 0;
+        let mut order = 0;
+        while idx < max_idx {
+            let remaining = max_idx - idx;
+            let block_size = calculate_block_size(max_order, order) << base_shift;
+            if remaining >= block_size {
+                buddies.set_free(order, idx >> base_shift, Ordering::Relaxed);
+                idx += block_size;
+            } else {
+                order += 1;
+
+                if order >= max_order {
+                    unreachable!()
+                }
+            }
+        }
+
+        Ok(buddies)
+    }
+
+    pub fn with_capacity(capacity: usize, multiplier: usize, a: A) -> Self {
+        assert!(
+            multiplier.is_power_of_two(),
+            "multiplier must be a power of two"
+        );
+
+        let max_order = max_order_for_capacity(capacity, multiplier)
+            .unwrap_or_else(|| panic!("capacity {} is too big", capacity));
+        Self::new_in(max_order, multiplier, Some(capacity), a)
+    }
+
+    pub fn try_with_capacity(
+        capacity: usize,
+        multiplier: usize,
+        a: A,
+    ) -> Result<Self, TryNewError> {
+        if !multiplier.is_power_of_two() {
+            return Err(TryNewError::InvalidParameters(
+                "multiplier must be a power of two",
+            ));
+        }
+        let max_order = max_order_for_capacity(capacity, multiplier)
+            .ok_or(TryNewError::InvalidParameters("capacity is too big"))?;
+        Self::try_new_in(max_order, multiplier, Some(capacity), a)
+    }
+
+    pub fn with_capacity_at(base: usize, capacity: usize, multiplier: usize, a: A) -> Self {
+        let mut buddies = Self::with_capacity(capacity, multiplier, a);
+        assert_eq!(
+            base % multiplier,
+            0,
+            "base {} is not a multiple of multiplier {}",
+            base,
+            multiplier
+        );
+        assert!(
+            base.checked_add(capacity).is_some(),
+            "base {} with capacity {} overflows",
+            base,
+            capacity
+        );
+        buddies.base = base;
+        buddies
+    }
+
+    /// create an instance with no free or allocated blocks from the raw parameters
+    ///
+    /// returns an error instead of panicking if the parameters are invalid or the blocks can't be
+    /// allocated
+    pub fn new_cleared_in(
+        max_order: usize,
+        base_shift: usize,
+        max_idx: usize,
+        base: usize,
+        allocations: isize,
+        a: A,
+    ) -> Result<Self, TryNewError> {
+        if max_order == 0 || max_order >= WORD_BITS {
+            return Err(TryNewError::InvalidParameters("invalid max_order"));
+        }
+        if base_shift >= WORD_BITS || (max_idx >> base_shift) << base_shift != max_idx {
+            return Err(TryNewError::InvalidParameters(
+                "max_idx is not a multiple of the multiplier",
+            ));
+        }
+        let default_max_blocks = calculate_block_size(max_order, 0);
+        let max_blocks = max_idx >> base_shift;
+        if max_blocks > default_max_blocks || max_blocks <= default_max_blocks / 2 {
+            return Err(TryNewError::InvalidParameters(
+                "max_idx doesn't fit max_order",
+            ));
+        }
+        if base & ((1 << base_shift) - 1) != 0 || base.checked_add(max_idx).is_none() {
+            return Err(TryNewError::InvalidParameters("invalid base"));
+        }
+        if allocations < 0 {
+            return Err(TryNewError::InvalidParameters(
+                "negative allocations counter",
+            ));
+        }
+
+        let mut buddies = Self::try_new_in(max_order, 1 << base_shift, Some(max_idx), a)?;
+        buddies.base = base;
+        buddies.allocations.store(allocations, Ordering::Relaxed);
+        for word in buddies.blocks.iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+        for free in buddies.free.iter() {
+            free.store(0, Ordering::Relaxed);
+        }
+        Ok(buddies)
+    }
+}
+
+/// a new value holding what `value` holds
+fn copy<T, V: Atomic<T>>(value: &V) -> V {
+    V::new(value.load(Ordering::Relaxed))
+}
+
+/// copies all bits and counters into a new bitmap
+///
+/// the copy is only consistent if there are no concurrent modifications while it's made.
+impl<A: AllocRef + Clone, B: Backend> Clone for RawBuddies<Vec<B::Usize, A>, B> {
+    fn clone(&self) -> Self {
+        let mut blocks = Vec::with_capacity_in(self.blocks.len(), self.blocks.alloc_ref().clone());
+        for word in self.blocks.iter() {
+            blocks.push(copy(word));
+        }
+
+        RawBuddies {
+            allocations: CachePadded(copy(&*self.allocations)),
+            #[cfg(feature = "stats")]
+            used: CachePadded(copy(&*self.used)),
+            #[cfg(feature = "stats")]
+            peak: CachePadded(copy(&*self.peak)),
+            #[cfg(feature = "stats")]
+            claim_retries: CachePadded(copy(&*self.claim_retries)),
+            reshaping: CachePadded(B::Usize::new(0)),
+            reshaped: CachePadded(B::Usize::new(0)),
+            free: CachePadded(core::array::from_fn(|order| copy(&self.free[order]))),
+            search_start: core::array::from_fn(|order| copy(&self.search_start[order])),
+            striding: copy(&self.striding),
+            scans: copy(&self.scans),
+            clustered: copy(&self.clustered),
+            // the quarantined blocks are still marked as allocated in the copied bits
+            #[cfg(feature = "quarantine")]
+            quarantine: quarantine::Quarantine {
+                next: copy(&self.quarantine.next),
+                slots: core::array::from_fn(|i| copy(&self.quarantine.slots[i])),
+            },
+            decommit: self.decommit,
+            rows: self.rows,
+            blocks,
+            max_order: self.max_order,
+            base_shift: self.base_shift,
+            max_idx: self.max_idx,
+            base: self.base,
+        }
+    }
+}
+
+#[cfg(feature = "static")]
+impl<const N: usize> RawBuddies<[AtomicUsize; N]> {
+    /// create an instance with inline storage, `N` must be `words(max_order)`
+    ///
+    /// this does the same as [RawBuddies::new_in] but panics without a message if the parameters
+    /// are invalid because formatting isn't available in const fns
+    pub const fn new_array(max_order: usize, multiplier: usize, max_idx: Option<usize>) -> Self {
+        assert!(max_order != 0 && max_order < WORD_BITS, "invalid max order");
+        assert!(
+            N == words(max_order),
+            "number of words doesn't match max order"
+        );
+        assert!(
+            multiplier.is_power_of_two(),
+            "multiplier must be a power of two"
+        );
+
+        let base_shift = multiplier.trailing_zeros() as usize;
+        let default_max_idx = match calculate_block_size(max_order, 0).checked_mul(multiplier) {
+            Some(default_max_idx) => default_max_idx,
+            None => panic!("capacity overflows"),
+        };
+
+        let max_idx = match max_idx {
+            Some(max_idx) => {
+                assert!(
+                    max_idx % multiplier == 0,
+                    "max_idx is not a multiple of multiplier"
+                );
+                assert!(max_idx <= default_max_idx, "max_idx is too big");
+                assert!(max_idx > default_max_idx / 2, "max_idx is too small");
+                max_idx
+            }
+            None => default_max_idx,
         };
+        #[cfg(feature = "compact")]
+        assert!(
+            max_idx >> base_shift <= Index::MAX as usize,
+            "too many blocks for the compact counters"
+        );
 
+        let rows = row_offsets(max_order, max_idx >> base_shift);
+
+        // atomics can't be modified in const fns, so the free bits are collected first
+        let mut bits = [0; N];
+        let mut counts = [0; WORD_BITS];
         let mut idx = 0;
         let mut order = 0;
         while idx < max_idx {
             let remaining = max_idx - idx;
             let block_size = calculate_block_size(max_order, order) << base_shift;
             if remaining >= block_size {
-                buddies[(order, idx >> base_shift)].store(true, Ordering::Relaxed);
+                let i = block_index(&rows, max_order, order, idx >> base_shift);
+                bits[i / WORD_BITS] |= 1 << (i % WORD_BITS);
+                if SUMMARY {
+                    let w = 2 * rows[max_order].div_ceil(WORD_BITS) + i / WORD_BITS / WORD_BITS;
+                    bits[w] |= 1 << (i / WORD_BITS % WORD_BITS);
+                }
+                counts[order] += 1;
                 idx += block_size;
             } else {
                 order += 1;
+            }
+        }
+
+        let mut blocks = [EMPTY_WORD; N];
+        let mut i = 0;
+        while i < N {
+            blocks[i] = AtomicUsize::new(bits[i]);
+            i += 1;
+        }
+        let mut free = [EMPTY_INDEX; WORD_BITS];
+        let mut order = 0;
+        while order < max_order {
+            free[order] = AtomicIndex::new(counts[order] as Index);
+            order += 1;
+        }
+
+        RawBuddies {
+            allocations: CachePadded(AtomicIsize::new(0)),
+            #[cfg(feature = "stats")]
+            used: CachePadded(AtomicUsize::new(0)),
+            #[cfg(feature = "stats")]
+            peak: CachePadded(AtomicUsize::new(0)),
+            #[cfg(feature = "stats")]
+            claim_retries: CachePadded(AtomicUsize::new(0)),
+            reshaping: CachePadded(AtomicUsize::new(0)),
+            reshaped: CachePadded(AtomicUsize::new(0)),
+            free: CachePadded(free),
+            search_start: [EMPTY_INDEX; WORD_BITS],
+            striding: AtomicBool::new(false),
+            scans: AtomicUsize::new(0),
+            clustered: AtomicBool::new(false),
+            #[cfg(feature = "quarantine")]
+            quarantine: quarantine::Quarantine {
+                next: AtomicUsize::new(0),
+                slots: [EMPTY_WORD; quarantine::QUARANTINE_LEN],
+            },
+            decommit: None,
+            rows,
+            blocks,
+            max_order,
+            base_shift,
+            max_idx,
+            base: 0,
+        }
+    }
+}
+
+impl<B: Backend, S: Storage<B::Usize>> RawBuddies<S, B> {
+    /// convert an index to an offset relative to base
+    fn offset(&self, idx: usize) -> usize {
+        assert!(
+            idx >= self.base,
+            "idx {} is below the base {}",
+            idx,
+            self.base
+        );
+        idx - self.base
+    }
+
+    fn calculate_block_size(&self, order: usize) -> usize {
+        calculate_block_size(self.max_order, order)
+    }
+
+    fn calculate_order_for_size(&self, size: usize) -> usize {
+        calculate_order_for_size(self.max_order, self.base_shift, size)
+    }
+
+    /// the position of the block in the storage
+    fn block(&self, order: usize, idx: usize) -> usize {
+        let block_size = self.calculate_block_size(order);
+        debug_assert_eq!(
+            idx & (block_size - 1),
+            0,
+            "trying to access child {} at order {} (alignment is off)",
+            idx,
+            order,
+        );
+        debug_assert!(
+            self.max_order >= order,
+            "order {} is too big for max order {}",
+            order,
+            self.max_order
+        );
+        debug_assert!(
+            idx < (self.max_idx >> self.base_shift),
+            "idx {} is greater or equal to max_idx {}",
+            (idx << self.base_shift),
+            self.max_idx
+        );
+
+        block_index(&self.rows, self.max_order, order, idx)
+    }
+
+    /// the number of stored blocks of an order
+    fn row_len(&self, order: usize) -> usize {
+        self.rows[order + 1] - self.rows[order]
+    }
+
+    /// the word containing the bit of the block at position `i` and the mask for the bit
+    fn word(&self, bit: Bit, i: usize) -> (&B::Usize, usize) {
+        let offset = match bit {
+            Bit::Available => 0,
+            Bit::Allocated => self.rows[self.max_order].div_ceil(WORD_BITS),
+        };
+        (
+            &self.blocks.as_words()[offset + i / WORD_BITS],
+            1 << (i % WORD_BITS),
+        )
+    }
+
+    /// the summary word containing the bit of word `w` of the free bits and the mask for the bit
+    fn summary(&self, w: usize) -> (&B::Usize, usize) {
+        let offset = 2 * self.rows[self.max_order].div_ceil(WORD_BITS);
+        (
+            &self.blocks.as_words()[offset + w / WORD_BITS],
+            1 << (w % WORD_BITS),
+        )
+    }
+
+    /// the first word of the free bits in `from..=to` whose summary bit is set
+    fn next_summarized(&self, from: usize, to: usize) -> Option<usize> {
+        if !SUMMARY {
+            return Some(from).filter(|&from| from <= to);
+        }
+        let mut w = from;
+        while w <= to {
+            let (summary, mask) = self.summary(w);
+            let bits = summary.load(Ordering::Relaxed) & !(mask - 1);
+            let first = w - w % WORD_BITS;
+            if bits != 0 {
+                return Some(first + bits.trailing_zeros() as usize).filter(|&w| w <= to);
+            }
+            w = first + WORD_BITS;
+        }
+        None
+    }
+
+    /// set the summary bit of word `w` of the free bits after a free bit was set in it
+    fn summarize(&self, w: usize) {
+        if !SUMMARY {
+            return;
+        }
+        let (summary, mask) = self.summary(w);
+        if summary.load(Ordering::SeqCst) & mask == 0 {
+            summary.fetch_or(mask, Ordering::SeqCst);
+        }
+    }
+
+    /// clear the summary bit of word `w` of the free bits after its last free bit was claimed
+    fn unsummarize(&self, w: usize) {
+        if !SUMMARY {
+            return;
+        }
+        let (summary, mask) = self.summary(w);
+        summary.fetch_and(!mask, Ordering::SeqCst);
+        // a block in the word might have been freed concurrently
+        if self.blocks.as_words()[w].load(Ordering::SeqCst) != 0 {
+            summary.fetch_or(mask, Ordering::SeqCst);
+        }
+    }
+
+    fn test(&self, bit: Bit, i: usize, order: Ordering) -> bool {
+        let (word, mask) = self.word(bit, i);
+        word.load(order) & mask != 0
+    }
+
+    fn set(&self, bit: Bit, i: usize, value: bool, order: Ordering) {
+        let (word, mask) = self.word(bit, i);
+        if value {
+            word.fetch_or(mask, order);
+        } else {
+            word.fetch_and(!mask, order);
+        }
+    }
+
+    /// the order and the index in the row of the block at position `pos` in a full tree
+    ///
+    /// the exported bitmaps always cover the full tree, so they don't depend on the storage.
+    fn tree_position(pos: usize) -> (usize, usize) {
+        let order = (pos + 1).ilog2() as usize;
+        (order, pos + 1 - (1 << order))
+    }
+
+    /// byte `i` of a bitmap of the full tree, bit `j` of it belongs to the block at position
+    /// `8 * i + j`. the blocks are ordered by order and then by index.
+    ///
+    /// this is only meaningful if there are no concurrent modifications
+    fn bitmap_byte(&self, bit: Bit, i: usize) -> u8 {
+        let mut byte = 0;
+        for j in 0..8 {
+            let (order, row_idx) = Self::tree_position(i * 8 + j);
+            if order < self.max_order
+                && row_idx < self.row_len(order)
+                && self.test(bit, self.rows[order] + row_idx, Ordering::Relaxed)
+            {
+                byte |= 1 << j;
+            }
+        }
+        byte
+    }
+
+    /// set the bits of byte `i` of a bitmap of the full tree, see [RawBuddies::bitmap_byte]
+    ///
+    /// the bits have to be cleared before and bits past the last block of the full tree must not
+    /// be set. fails if a block isn't stored because it's out of bounds.
+    fn set_bitmap_byte(&self, bit: Bit, i: usize, byte: u8) -> Result<(), IntegrityError> {
+        for j in (0..8).filter(|j| byte & (1 << j) != 0) {
+            let (order, row_idx) = Self::tree_position(i * 8 + j);
+            if row_idx >= self.row_len(order) {
+                let block_size = self.calculate_block_size(order);
+                return Err(IntegrityError::OutOfBounds {
+                    order,
+                    idx: self.base + ((row_idx * block_size) << self.base_shift),
+                });
+            }
+            self.set(bit, self.rows[order] + row_idx, true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn is_free(&self, order: usize, idx: usize, ordering: Ordering) -> bool {
+        self.test(Bit::Available, self.block(order, idx), ordering)
+    }
+
+    /// mark a block as free, this releases its memory (see [RawBuddies::claim])
+    fn set_free(&self, order: usize, idx: usize, ordering: Ordering) {
+        self.free[order].fetch_add(1, Ordering::Relaxed);
+        self.search_start[order].fetch_min(idx as Index, Ordering::Relaxed);
+        let i = self.block(order, idx);
+        if !SUMMARY {
+            self.set(Bit::Available, i, true, ordering)
+        } else {
+            // see [summary_words]
+            self.set(Bit::Available, i, true, Ordering::SeqCst);
+            self.summarize(i / WORD_BITS);
+        }
+    }
+
+    /// returns false if there's definitely no free block in the order
+    fn may_have_free(&self, order: usize) -> bool {
+        self.free[order].load(Ordering::Relaxed) != 0
+    }
+
+    /// mark a free block as not free, returns false if it wasn't free
+    ///
+    /// claiming a block acquires the ownership of its memory, every store marking a block as
+    /// free releases it. this way writes to the memory by the previous owner happen before the
+    /// accesses by the next owner, even if the block was split or merged in between because
+    /// the threads doing that claim and release the blocks in the same way. a failed claim
+    /// doesn't transfer anything, so it's relaxed.
+    ///
+    /// all accesses to the words are read-modify-write operations, so they continue the release
+    /// sequences of earlier stores to the same word even if they change the bits of other blocks.
+    ///
+    /// the `allocated` bits and the counters are only bookkeeping and always relaxed.
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::{AllocInit, AllocRef, Global, Layout};
+    /// use buddy_allocator::BuddyAllocator;
+    /// use std::{sync::Arc, thread};
+    ///
+    /// const ROUNDS: u8 = 100;
+    ///
+    /// // a single block that is passed between the threads only through the allocator
+    /// let allocator = Arc::new(BuddyAllocator::try_new(1, 64, None, Global).unwrap());
+    /// let layout = Layout::from_size_align(64, 1).unwrap();
+    ///
+    /// let memory = (&*allocator).alloc(layout, AllocInit::Zeroed).unwrap();
+    /// unsafe { (&*allocator).dealloc(memory.ptr, layout) };
+    /// # #[cfg(feature = "quarantine")] allocator.flush_quarantine();
+    ///
+    /// let threads = (0..2)
+    ///     .map(|_| {
+    ///         let allocator = allocator.clone();
+    ///         thread::spawn(move || {
+    ///             for _ in 0..ROUNDS {
+    ///                 let memory = loop {
+    ///                     if let Ok(memory) = (&*allocator).alloc(layout, AllocInit::Uninitialized) {
+    ///                         break memory;
+    ///                     }
+    ///                     thread::yield_now();
+    ///                 };
+    ///                 let bytes = unsafe { std::slice::from_raw_parts_mut(memory.ptr.as_ptr(), 64) };
+    ///                 let value = bytes[0];
+    ///                 assert!(bytes.iter().all(|&byte| byte == value));
+    ///                 bytes.iter_mut().for_each(|byte| *byte = value + 1);
+    ///                 unsafe { (&*allocator).dealloc(memory.ptr, layout) };
+    ///                 # #[cfg(feature = "quarantine")] allocator.flush_quarantine();
+    ///             }
+    ///         })
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// for thread in threads {
+    ///     thread.join().unwrap();
+    /// }
+    ///
+    /// // every round saw the writes of the previous one
+    /// let memory = (&*allocator).alloc(layout, AllocInit::Uninitialized).unwrap();
+    /// let bytes = unsafe { std::slice::from_raw_parts(memory.ptr.as_ptr(), 64) };
+    /// assert!(bytes.iter().all(|&byte| byte == 2 * ROUNDS));
+    /// ```
+    fn claim(&self, order: usize, idx: usize) -> bool {
+        self.claim_ordered(order, idx, Ordering::Acquire)
+    }
+
+    fn claim_ordered(&self, order: usize, idx: usize, success: Ordering) -> bool {
+        let i = self.block(order, idx);
+        self.claim_in_word(order, i / WORD_BITS, 1 << (i % WORD_BITS), success)
+    }
+
+    /// claim the block of `order` whose free bit is `mask` in word `w` of the free bits
+    fn claim_in_word(&self, order: usize, w: usize, mask: usize, success: Ordering) -> bool {
+        let mut _retries = 0;
+        let remaining = claim_bit(&self.blocks.as_words()[w], mask, success, &mut _retries);
+        #[cfg(feature = "stats")]
+        if _retries != 0 {
+            self.claim_retries.fetch_add(_retries, Ordering::Relaxed);
+        }
+        match remaining {
+            Some(remaining) => {
+                self.free[order].fetch_sub(1, Ordering::Relaxed);
+                if remaining == 0 {
+                    self.unsummarize(w);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn is_allocated(&self, order: usize, idx: usize) -> bool {
+        self.test(Bit::Allocated, self.block(order, idx), Ordering::Relaxed)
+    }
+
+    fn set_allocated(&self, order: usize, idx: usize, value: bool) {
+        self.set(
+            Bit::Allocated,
+            self.block(order, idx),
+            value,
+            Ordering::Relaxed,
+        )
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.max_idx
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn num_allocations(&self) -> usize {
+        let value = self.allocations.load(Ordering::Relaxed);
+        if value < 0 {
+            // poisoned by `is_unused`
+            0
+        } else {
+            value as usize
+        }
+    }
+
+    /// poison the instance if there are no allocations
+    ///
+    /// a poisoned counter is `isize::MIN` and stays that way, allocations check it before
+    /// incrementing it and deallocations panic.
+    pub fn is_unused(&self) -> bool {
+        // relaxed is enough because the counter doesn't publish any other memory
+        self.allocations
+            .compare_exchange(0, isize::MIN, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.allocations.load(Ordering::Relaxed) < 0
+    }
+
+    /// undo a successful [RawBuddies::is_unused]
+    pub fn unpoison(&self) {
+        let _ =
+            self.allocations
+                .compare_exchange(isize::MIN, 0, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    /// count a new allocation, returns false if the instance is poisoned
+    ///
+    /// while the allocation is counted the counter isn't zero, so it can't be poisoned before
+    /// the allocation fails and decrements it again.
+    fn begin_allocation(&self) -> bool {
+        let mut current = self.allocations.load(Ordering::Relaxed);
+        loop {
+            if current < 0 {
+                return false;
+            }
+            match self.allocations.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// stop counting a deallocated allocation
+    ///
+    /// # Panics
+    /// panics if the instance is poisoned, it had no allocations when it was poisoned
+    fn end_allocation(&self) {
+        let mut current = self.allocations.load(Ordering::Relaxed);
+        loop {
+            assert!(current >= 0, "deallocation after is_unused returned true");
+            match self.allocations.compare_exchange_weak(
+                current,
+                current - 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// announce a claim that may hide free blocks from searches until [RawBuddies::end_reshape]
+    ///
+    /// a split claims a free block before it frees the halves that aren't allocated, a merge
+    /// claims free buddies before it frees their parent and a grow claims free buddies that it
+    /// may have to free again. a search in between finds none of them, so a search that fails
+    /// while one of them is in progress or after one finished is repeated, see
+    /// [RawBuddies::search].
+    ///
+    /// the fence orders the counter before the claims, so a search that saw a claimed block or
+    /// a decremented free counter also sees the counter after its own fence.
+    fn begin_reshape(&self) {
+        self.reshaping.fetch_add(1, Ordering::SeqCst);
+        B::fence(Ordering::Release);
+    }
+
+    /// end a split, merge or grow after the blocks it didn't keep were marked as free again
+    fn end_reshape(&self) {
+        self.reshaped.fetch_add(1, Ordering::SeqCst);
+        self.reshaping.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// end a split, merge or grow that kept all blocks it claimed
+    fn cancel_reshape(&self) {
+        self.reshaping.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// claim a free block to split it, see [RawBuddies::begin_reshape]
+    fn claim_to_split(&self, claim: impl FnOnce() -> bool) -> bool {
+        self.begin_reshape();
+        let claimed = claim();
+        if !claimed {
+            self.cancel_reshape();
+        }
+        claimed
+    }
+
+    /// repeat `search` until it succeeds or no split, merge or grow could have hidden a free
+    /// block from it
+    ///
+    /// a search doesn't fail just because the blocks it looked for were moved around
+    /// concurrently, but it waits for the splits and merges in progress when it can't find a
+    /// block.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    /// use std::{sync::Arc, thread};
+    ///
+    /// // room for the two units of every thread
+    /// let capacity = 8;
+    /// # // and for the quarantined ones
+    /// # #[cfg(feature = "quarantine")] let capacity = capacity + 16;
+    /// let buddies = Arc::new(Buddies::with_capacity(capacity, 1));
+    ///
+    /// // the blocks are split and merged all the time, but there's always a free one
+    /// let threads = (0..4)
+    ///     .map(|_| {
+    ///         let buddies = buddies.clone();
+    ///         thread::spawn(move || {
+    ///             for _ in 0..1000 {
+    ///                 let first = buddies.allocate(1, 1).unwrap();
+    ///                 let second = buddies.allocate(1, 1).unwrap();
+    ///                 buddies.deallocate(first, 1);
+    ///                 buddies.deallocate(second, 1);
+    ///             }
+    ///         })
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// for thread in threads {
+    ///     thread.join().unwrap();
+    /// }
+    /// ```
+    fn search<T>(&self, mut search: impl FnMut() -> Option<T>) -> Option<T> {
+        loop {
+            let reshaped = self.reshaped.load(Ordering::SeqCst);
+            if let Some(found) = search() {
+                return Some(found);
+            }
+
+            // see [RawBuddies::begin_reshape]
+            B::fence(Ordering::Acquire);
+            let reshaping = self.reshaping.load(Ordering::SeqCst);
+            if reshaping == 0 && self.reshaped.load(Ordering::SeqCst) == reshaped {
+                return None;
+            }
+            if reshaping != 0 {
+                spin_loop();
+            }
+        }
+    }
+
+    /// the sum of the sizes of the free blocks
+    ///
+    /// this is only meaningful if there are no concurrent modifications
+    pub fn free_bytes(&self) -> usize {
+        let mut free = 0;
+        let mut i = 0;
+        for order in 0..self.max_order {
+            let block_size = self.calculate_block_size(order) << self.base_shift;
+            for _ in 0..self.row_len(order) {
+                if self.test(Bit::Available, i, Ordering::Relaxed) {
+                    free += block_size;
+                }
+                i += 1;
+            }
+        }
+        free
+    }
+
+    /// every block in the rows as `(order, idx, is_free)`, ordered by order and then by index
+    pub fn bits(&self) -> impl Iterator<Item = (usize, usize, bool)> + '_ {
+        (0..self.max_order).flat_map(move |order| {
+            let block_size = self.calculate_block_size(order) << self.base_shift;
+            let row = self.rows[order];
+            (0..self.row_len(order)).map(move |pos| {
+                let is_free = self.test(Bit::Available, row + pos, Ordering::Relaxed);
+                (order, self.base + pos * block_size, is_free)
+            })
+        })
+    }
+
+    /// the size of the biggest free block, 0 if nothing is free
+    ///
+    /// this is only meaningful if there are no concurrent modifications
+    pub fn largest_free_block(&self) -> usize {
+        let mut i = 0;
+        for order in 0..self.max_order {
+            let row_len = self.row_len(order);
+            if (i..i + row_len).any(|i| self.test(Bit::Available, i, Ordering::Relaxed)) {
+                return self.calculate_block_size(order) << self.base_shift;
+            }
+            i += row_len;
+        }
+        0
+    }
+
+    #[cfg(feature = "stats")]
+    fn add_used(&self, size: usize) {
+        let used = self.used.fetch_add(size, Ordering::Relaxed) + size;
+        let mut peak = self.peak.load(Ordering::Relaxed);
+        while peak < used {
+            match self
+                .peak
+                .compare_exchange_weak(peak, used, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => peak = current,
+            }
+        }
+    }
+
+    #[cfg(not(feature = "stats"))]
+    fn add_used(&self, _size: usize) {}
+
+    #[cfg(feature = "stats")]
+    fn sub_used(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    #[cfg(not(feature = "stats"))]
+    fn sub_used(&self, _size: usize) {}
+
+    #[cfg(feature = "stats")]
+    pub fn used_bytes(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn peak_used_bytes(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn reset_peak(&self) {
+        self.peak.store(self.used_bytes(), Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn claim_retries(&self) -> usize {
+        self.claim_retries.load(Ordering::Relaxed)
+    }
+
+    pub fn set_placement(&self, placement: Placement) {
+        self.clustered
+            .store(placement == Placement::Clustered, Ordering::Relaxed);
+    }
+
+    pub fn set_scan_start(&self, scan_start: ScanStart) {
+        self.striding
+            .store(scan_start == ScanStart::Striding, Ordering::Relaxed);
+    }
+
+    /// recalculate the free blocks, the summary and the used bytes from the bits
+    ///
+    /// this is only meaningful if there are no concurrent modifications
+    pub fn recount(&self) {
+        #[cfg(feature = "stats")]
+        let mut used = 0;
+        let mut i = 0;
+        for order in 0..self.max_order {
+            let mut free = 0;
+            for _ in 0..self.row_len(order) {
+                if self.test(Bit::Available, i, Ordering::Relaxed) {
+                    free += 1;
+                }
+                #[cfg(feature = "stats")]
+                if self.test(Bit::Allocated, i, Ordering::Relaxed) {
+                    used += self.calculate_block_size(order) << self.base_shift;
+                }
+                i += 1;
+            }
+            self.free[order].store(free as Index, Ordering::Relaxed);
+            self.search_start[order].store(0, Ordering::Relaxed);
+        }
+        if SUMMARY {
+            for w in 0..self.rows[self.max_order].div_ceil(WORD_BITS) {
+                let (summary, mask) = self.summary(w);
+                if self.blocks.as_words()[w].load(Ordering::Relaxed) != 0 {
+                    summary.fetch_or(mask, Ordering::Relaxed);
+                } else {
+                    summary.fetch_and(!mask, Ordering::Relaxed);
+                }
+            }
+        }
+        #[cfg(feature = "stats")]
+        {
+            self.used.store(used, Ordering::Relaxed);
+            self.peak.store(used, Ordering::Relaxed);
+        }
+    }
+
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// for &multiplier in &[1, 2, 4, 8] {
+    ///     let buddies = Buddies::new(5, multiplier, None);
+    ///     let real = |size| buddies.real_size_for_allocation(size);
+    ///     assert_eq!(real(0), multiplier);
+    ///     for i in 0..=buddies.capacity() {
+    ///         assert!(i <= real(i), "{} -> {}", i, real(i));
+    ///         assert_eq!(real(real(i)), real(i), "{} isn't idempotent", i);
+    ///         if i != 0 {
+    ///             assert!(real(i - 1) <= real(i), "{} isn't monotonic", i);
+    ///         }
+    ///
+    ///         // every size maps to the same block for allocating and deallocating
+    ///         let idx = buddies.allocate(i, 1).unwrap();
+    ///         assert_eq!(buddies.allocation_size_at(idx), Some(real(i)));
+    ///         buddies.deallocate(idx, i);
+    ///         # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///         assert_eq!(buddies.num_allocations(), 0);
+    ///         assert_eq!(buddies.allocate(buddies.capacity(), 1), Some(0));
+    ///         buddies.deallocate(0, buddies.capacity());
+    ///         # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///     }
+    /// }
+    /// ```
+    pub fn real_size_for_allocation(&self, size: usize) -> usize {
+        self.try_real_size_for_allocation(size).unwrap_or_else(|| {
+            panic!(
+                "size {} is too big (the biggest allocation has size {})",
+                size,
+                self.max_allocation_size()
+            )
+        })
+    }
+
+    pub fn try_real_size_for_allocation(&self, size: usize) -> Option<usize> {
+        let order = self.checked_order_for_size(size)?;
+        let real_size = self.calculate_block_size(order) << self.base_shift;
+        if real_size > self.max_idx {
+            return None;
+        }
+        Some(real_size)
+    }
+
+    /// whether allocations of `size` take a block of exactly that size
+    pub fn is_exact_size(&self, size: usize) -> bool {
+        self.try_real_size_for_allocation(size) == Some(size)
+    }
+
+    /// the biggest block that fits into the capacity
+    fn max_allocation_size(&self) -> usize {
+        1 << self.max_idx.ilog2()
+    }
+
+    pub fn order_for_size(&self, size: usize) -> usize {
+        self.checked_order_for_size(size).unwrap_or_else(|| {
+            panic!(
+                "size {} is too big (the capacity is {})",
+                size, self.max_idx
+            )
+        })
+    }
+
+    /// the order for an allocation of `size`, `None` if `size` is bigger than the capacity
+    ///
+    /// the capacity is never bigger than the whole tree, so the order can't underflow.
+    fn checked_order_for_size(&self, size: usize) -> Option<usize> {
+        if size > self.max_idx {
+            return None;
+        }
+        Some(self.calculate_order_for_size(size))
+    }
+
+    pub fn size_for_order(&self, order: usize) -> usize {
+        self.check_order(order);
+        self.calculate_block_size(order) << self.base_shift
+    }
+
+    fn check_order(&self, order: usize) {
+        assert!(
+            order < self.max_order,
+            "order {} is out of range (max order is {})",
+            order,
+            self.max_order
+        );
+    }
+
+    /// check that the block of `order` at `offset` is aligned and lies within the capacity
+    ///
+    /// [RawBuddies::block] only checks this in debug builds, but out of bounds blocks share
+    /// their words with the blocks in bounds
+    fn check_block(&self, offset: usize, order: usize) {
+        let size = self.calculate_block_size(order) << self.base_shift;
+        assert_eq!(
+            offset & (size - 1),
+            0,
+            "{} is not aligned to its size {}",
+            self.base + offset,
+            size
+        );
+        assert!(
+            offset <= self.max_idx && size <= self.max_idx - offset,
+            "{} with size {} is out of bounds",
+            self.base + offset,
+            size
+        );
+    }
+
+    pub fn allocate_with_size(&self, size: usize, align: usize) -> Option<usize> {
+        self.allocate_near_with_size(size, align, None)
+    }
+
+    /// allocate a block, if there's a hint blocks close to it are preferred
+    pub fn allocate_near_with_size(
+        &self,
+        size: usize,
+        align: usize,
+        hint: Option<usize>,
+    ) -> Option<usize> {
+        let order = self.order_for_size(size);
+        self.allocate_order(order, align, hint)
+    }
+
+    /// allocate a block of a given order, returns `None` if the order is out of range
+    ///
+    /// on a full tree this fails without scanning because the free counters of all orders are
+    /// zero.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// // scanning the 2^22 blocks for each of these allocations would take minutes
+    /// let buddies = Buddies::new(22, 1, None);
+    /// assert_eq!(buddies.allocate_largest(), Some((0, 1 << 21)));
+    /// for _ in 0..100_000 {
+    ///     assert_eq!(buddies.allocate(1, 1), None);
+    /// }
+    /// buddies.deallocate(0, 1 << 21);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocate(1, 1), Some(0));
+    /// ```
+    pub fn allocate_order(&self, order: usize, align: usize, hint: Option<usize>) -> Option<usize> {
+        if order >= self.max_order {
+            return None;
+        }
+
+        if !self.begin_allocation() {
+            return None;
+        }
+
+        let res = self.search(|| {
+            // neither a block of this order nor a bigger one to split is free
+            if !(0..=order).any(|order| self.may_have_free(order)) {
+                return None;
+            }
+            match hint {
+                Some(hint) => self.allocate_near(order, align, hint),
+                None => self.allocate(order, align),
+            }
+        });
+        if let Some(idx) = res {
+            self.set_allocated(order, idx >> self.base_shift, true);
+            self.add_used(self.calculate_block_size(order) << self.base_shift);
+        } else {
+            self.allocations.fetch_sub(1, Ordering::Relaxed);
+        }
+        res.map(|idx| self.base + idx)
+    }
+
+    /// claim the biggest free block, returns its index and size
+    pub fn allocate_largest(&self) -> Option<(usize, usize)> {
+        if !self.begin_allocation() {
+            return None;
+        }
+
+        let found = self.search(|| {
+            (0..self.max_order).find_map(|order| {
+                let block_size = self.calculate_block_size(order);
+                self.scan(order, 0, block_size, Claim::Allocation)
+                    .map(|idx| (order, idx))
+            })
+        });
+        let (order, idx) = match found {
+            Some(found) => found,
+            None => {
+                self.allocations.fetch_sub(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+
+        let block_size = self.calculate_block_size(order);
+        self.set_allocated(order, idx, true);
+        self.add_used(block_size << self.base_shift);
+        Some((
+            self.base + (idx << self.base_shift),
+            block_size << self.base_shift,
+        ))
+    }
+
+    fn allocate(&self, order: usize, align_size: usize) -> Option<usize> {
+        assert!(align_size <= self.max_idx, "align is too big");
+        assert!(align_size.is_power_of_two(), "align is not a power of two");
+
+        // alignment is relative to the absolute index, so the first aligned block is not
+        // necessarily at offset 0
+        let align_block_size = (align_size >> self.base_shift).max(1);
+        let align_offset = (self.base.wrapping_neg() & (align_size - 1)) >> self.base_shift;
+        self.allocate_aligned(order, align_block_size, align_offset)
+    }
+
+    /// allocate a block as close as possible to the index `hint`
+    ///
+    /// first the aligned block containing the hint is tried (splitting its ancestors), then the
+    /// free blocks of the same order in increasing distance and finally everything else.
+    fn allocate_near(&self, order: usize, align_size: usize, hint: usize) -> Option<usize> {
+        assert!(align_size <= self.max_idx, "align is too big");
+        assert!(align_size.is_power_of_two(), "align is not a power of two");
+
+        let block_size = self.calculate_block_size(order);
+        let align_block_size = (align_size >> self.base_shift).max(1);
+        let align_offset = (self.base.wrapping_neg() & (align_size - 1)) >> self.base_shift;
+        let max_blocks = self.max_idx >> self.base_shift;
+
+        if align_offset & (block_size - 1) == 0 && align_offset + block_size <= max_blocks {
+            let inc_size = block_size.max(align_block_size);
+            let last =
+                align_offset + (max_blocks - align_offset - block_size) / inc_size * inc_size;
+
+            // the aligned block containing the hint
+            let hint = hint.saturating_sub(self.base) >> self.base_shift;
+            let idx = align_offset + hint.saturating_sub(align_offset) / inc_size * inc_size;
+            let idx = idx.min(last);
+            if self.allocate_at(order, idx << self.base_shift) {
+                return Some(idx << self.base_shift);
+            }
+
+            // spiral outwards
+            let mut below = idx;
+            let mut above = idx;
+            while self.may_have_free(order) && (below > align_offset || above < last) {
+                if above < last {
+                    above += inc_size;
+                    if self.claim(order, above) {
+                        return Some(above << self.base_shift);
+                    }
+                }
+                if below > align_offset {
+                    below -= inc_size;
+                    if self.claim(order, below) {
+                        return Some(below << self.base_shift);
+                    }
+                }
+            }
+        }
+
+        self.allocate(order, align_size)
+    }
+
+    /// allocate a block whose index is `align_offset` modulo `align_block_size`
+    ///
+    /// if no aligned block of this order is free the aligned blocks of the parent order are
+    /// searched, so an aligned block inside a bigger free block that starts at an unaligned index
+    /// is found by splitting it.
+    ///
+    /// this doesn't recurse, it scans at most `order + 1` orders and then splits at most `order`
+    /// blocks.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(8, 1, None);
+    /// assert_eq!(buddies.allocate(1, 32), Some(0));
+    /// assert_eq!(buddies.allocate(1, 32), Some(32));
+    /// assert_eq!(buddies.allocate(1, 32), Some(64));
+    /// assert_eq!(buddies.allocate(1, 32), Some(96));
+    /// assert_eq!(buddies.allocate(1, 32), None);
+    /// assert_eq!(buddies.allocate(1, 1), Some(1));
+    ///
+    /// // the only free aligned index is inside of the free block 64..128
+    /// let buddies = Buddies::new(8, 1, None);
+    /// assert_eq!(buddies.allocate(1, 1), Some(0));
+    /// assert_eq!(buddies.allocate(32, 1), Some(32));
+    /// assert_eq!(buddies.allocate(2, 64), Some(64));
+    /// assert_eq!(buddies.allocate(2, 64), None);
+    /// buddies.deallocate(64, 2);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocate(16, 32), Some(64));
+    /// assert_eq!(buddies.allocate(4, 32), Some(96));
+    ///
+    /// // alignment relative to an unaligned base
+    /// let buddies = Buddies::with_capacity_at(8, 64, 1);
+    /// assert_eq!(buddies.allocate(1, 32), Some(32));
+    /// assert_eq!(buddies.allocate(1, 32), Some(64));
+    /// assert_eq!(buddies.allocate(1, 32), None);
+    /// ```
+    fn allocate_aligned(
+        &self,
+        order: usize,
+        align_block_size: usize,
+        align_offset: usize,
+    ) -> Option<usize> {
+        if self.clustered.load(Ordering::Relaxed) {
+            return self.allocate_clustered(order, align_block_size, align_offset);
+        }
+
+        // search the orders upwards until a free block containing an aligned block is found
+        let mut found_order = order;
+        let mut found_align_offset = align_offset;
+        let idx = loop {
+            let block_size = self.calculate_block_size(found_order);
+            if found_align_offset & (block_size - 1) != 0 {
+                // no block of this order starts at an aligned index
+                return None;
+            }
+            let inc_size = block_size.max(align_block_size);
+
+            let claim = if found_order == order {
+                Claim::Allocation
+            } else {
+                Claim::Split
+            };
+            if let Some(idx) = self.scan(found_order, found_align_offset, inc_size, claim) {
+                break idx;
+            }
+            if found_order == 0 {
+                return None;
+            }
+
+            // a parent containing an aligned block
+            found_align_offset &= !block_size;
+            found_order -= 1;
+        };
+
+        let idx = self.split_aligned(found_order, idx, order, align_offset);
+        if found_order != order {
+            self.end_reshape();
+        }
+        Some(idx << self.base_shift)
+    }
+
+    /// split the claimed block at `idx` of `found_order` down to `order`, freeing the halves
+    /// without the aligned block, returns the block index of the aligned block
+    fn split_aligned(
+        &self,
+        found_order: usize,
+        mut idx: usize,
+        order: usize,
+        align_offset: usize,
+    ) -> usize {
+        for order in found_order + 1..=order {
+            let block_size = self.calculate_block_size(order);
+            idx |= align_offset & block_size;
+            self.set_free(order, idx ^ block_size, Ordering::Release);
+        }
+        idx
+    }
+
+    /// like [RawBuddies::allocate_aligned], but takes the free block containing an aligned block
+    /// with the lowest index, see [Placement::Clustered]
+    /// ```
+    /// use buddy_allocator::{Buddies, Placement};
+    ///
+    /// let buddies = Buddies::new(5, 1, None);
+    /// buddies.set_placement(Placement::Clustered);
+    /// assert!(buddies.allocate_at(4, 0));
+    /// assert!(buddies.allocate_at(2, 8));
+    /// // 4..8 is split although 10..12 has the right size
+    /// assert_eq!(buddies.allocate(2, 1), Some(4));
+    /// assert_eq!(buddies.allocate(1, 1), Some(6));
+    /// // 7 isn't aligned
+    /// assert_eq!(buddies.allocate(1, 2), Some(10));
+    /// assert_eq!(buddies.allocate(4, 4), Some(12));
+    /// assert_eq!(buddies.allocate(1, 1), Some(7));
+    /// ```
+    fn allocate_clustered(
+        &self,
+        order: usize,
+        align_block_size: usize,
+        align_offset: usize,
+    ) -> Option<usize> {
+        loop {
+            // check every order, only blocks below the best one so far are of interest
+            let mut best = None;
+            let mut limit = self.max_idx >> self.base_shift;
+            let mut found_order = order;
+            let mut found_align_offset = align_offset;
+            loop {
+                let block_size = self.calculate_block_size(found_order);
+                if found_align_offset & (block_size - 1) != 0 {
+                    break;
+                }
+                let inc_size = block_size.max(align_block_size);
+                if let Some(idx) =
+                    self.find_lowest(found_order, found_align_offset, inc_size, limit)
+                {
+                    best = Some((found_order, idx));
+                    limit = idx;
+                }
+                if found_order == 0 {
+                    break;
+                }
+                found_align_offset &= !block_size;
+                found_order -= 1;
+            }
+
+            let (found_order, idx) = best?;
+            // otherwise it was taken concurrently, search again
+            if found_order == order {
+                if self.claim(found_order, idx) {
+                    return Some(idx << self.base_shift);
+                }
+            } else if self.claim_to_split(|| self.claim(found_order, idx)) {
+                let idx = self.split_aligned(found_order, idx, order, align_offset);
+                self.end_reshape();
+                return Some(idx << self.base_shift);
+            }
+        }
+    }
+
+    /// the lowest free block of the order at `align_offset + k * inc_size` below `limit`, returns
+    /// its block index without claiming it
+    fn find_lowest(
+        &self,
+        order: usize,
+        align_offset: usize,
+        inc_size: usize,
+        limit: usize,
+    ) -> Option<usize> {
+        let block_size = self.calculate_block_size(order);
+        let max_blocks = self.max_idx >> self.base_shift;
+        if align_offset + block_size > max_blocks || align_offset >= limit {
+            return None;
+        }
+        let upper = (max_blocks - block_size).min(limit - 1);
+        let last = align_offset + (upper - align_offset) / inc_size * inc_size;
+        self.scan_row(
+            order,
+            align_offset / block_size,
+            last / block_size,
+            inc_size / block_size,
+            Claim::No,
+        )
+        .map(|pos| pos * block_size)
+    }
+
+    /// claim a free block of the order at `align_offset + k * inc_size`, returns its block index
+    ///
+    /// the scan starts at the search start of the order and wraps around, so every candidate is
+    /// checked once. without concurrent modifications this finds the lowest free candidate.
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let buddies = Buddies::new(6, 1, None);
+    /// for i in 0..16 {
+    ///     assert_eq!(buddies.allocate(1, 1), Some(i));
+    /// }
+    /// // freeing pulls the search start back
+    /// buddies.deallocate(3, 1);
+    /// buddies.deallocate(9, 1);
+    /// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// assert_eq!(buddies.allocate(1, 1), Some(3));
+    /// assert_eq!(buddies.allocate(1, 1), Some(9));
+    /// assert_eq!(buddies.allocate(1, 1), Some(16));
+    /// // an aligned scan doesn't move it, so the unaligned free block 17 is still found
+    /// assert_eq!(buddies.allocate(1, 4), Some(20));
+    /// assert_eq!(buddies.allocate(1, 1), Some(17));
+    ///
+    /// // the candidates are spread over several words
+    /// let buddies = Buddies::new(9, 1, None);
+    /// for i in 0..256 {
+    ///     assert_eq!(buddies.allocate(1, 1), Some(i));
+    /// }
+    /// for &idx in &[63, 64, 130, 192, 255] {
+    ///     buddies.deallocate(idx, 1);
+    ///     # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    /// }
+    /// assert_eq!(buddies.allocate(1, 64), Some(64));
+    /// assert_eq!(buddies.allocate(1, 64), Some(192));
+    /// assert_eq!(buddies.allocate(1, 64), None);
+    /// assert_eq!(buddies.allocate(1, 2), Some(130));
+    /// assert_eq!(buddies.allocate(1, 1), Some(63));
+    /// assert_eq!(buddies.allocate(1, 1), Some(255));
+    /// assert_eq!(buddies.allocate(1, 1), None);
+    /// ```
+    fn scan(
+        &self,
+        order: usize,
+        align_offset: usize,
+        inc_size: usize,
+        claim: Claim,
+    ) -> Option<usize> {
+        let block_size = self.calculate_block_size(order);
+        let max_blocks = self.max_idx >> self.base_shift;
+        if align_offset + block_size > max_blocks {
+            return None;
+        }
+        let last = align_offset + (max_blocks - align_offset - block_size) / inc_size * inc_size;
+
+        let striding = self.striding.load(Ordering::Relaxed);
+        let hint = widen(self.search_start[order].load(Ordering::Relaxed));
+        let start = align_offset + hint.saturating_sub(align_offset) / inc_size * inc_size;
+        let start = if start > last { align_offset } else { start };
+        let start = if striding {
+            // spread consecutive scans evenly over the candidates from the hint on
+            let candidates = (last - start) / inc_size + 1;
+            let scan = self.scans.fetch_add(1, Ordering::Relaxed);
+            start + scan.wrapping_mul(GOLDEN_RATIO) % candidates * inc_size
+        } else {
+            start
+        };
+        // only a scan checking every block of the order from the hint proves that the skipped
+        // ones are taken
+        let exhaustive = !striding && align_offset == 0 && inc_size == block_size;
 
-                if order >= max_order {
-                    unreachable!()
-                }
+        let stride = inc_size / block_size;
+        let (first, start, last) = (
+            align_offset / block_size,
+            start / block_size,
+            last / block_size,
+        );
+        if let Some(pos) = self.scan_row(order, start, last, stride, claim) {
+            let idx = pos * block_size;
+            if exhaustive {
+                // a concurrent deallocation might have moved it back, keep that
+                let _ = self.search_start[order].compare_exchange(
+                    hint as Index,
+                    (idx + block_size) as Index,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
             }
+            return Some(idx);
         }
-
-        buddies
+        if start == first {
+            return None;
+        }
+        self.scan_row(order, first, start - stride, stride, claim)
+            .map(|pos| pos * block_size)
     }
 
-    pub fn with_capacity(capacity: usize, multiplier: usize, a: A) -> Self {
-        const HUGE_ORDER: usize = 100;
+    /// claim a free block of the order at the positions `from + k * stride` up to `to` in the
+    /// row, returns its position
+    ///
+    /// with [Claim::No] the lowest free block is returned but not claimed.
+    ///
+    /// a whole word of free bits is checked with a single load, the candidates that are free are
+    /// found with `trailing_zeros` and only those are claimed. the words without free blocks are
+    /// skipped with the summary, see [summary_words].
+    fn scan_row(
+        &self,
+        order: usize,
+        from: usize,
+        to: usize,
+        stride: usize,
+        claim: Claim,
+    ) -> Option<usize> {
+        let row = self.rows[order];
+        // a mask with the bits at every `stride`th position starting at bit 0
+        let pattern = if stride < WORD_BITS {
+            usize::MAX / ((1 << stride) - 1)
+        } else {
+            1
+        };
 
-        assert!(
-            multiplier.is_power_of_two(),
-            "multiplier must be a power of two"
-        );
+        let mut pos = from;
+        while pos <= to && self.may_have_free(order) {
+            let w = (row + pos) / WORD_BITS;
+            match self.next_summarized(w, (row + to) / WORD_BITS) {
+                Some(next) if next == w => {}
+                Some(next) => {
+                    // skip to the first candidate in the next word that may have free blocks
+                    pos += (next * WORD_BITS - row - pos).div_ceil(stride) * stride;
+                    continue;
+                }
+                None => break,
+            }
 
-        let base_shift = multiplier.trailing_zeros() as usize;
+            let word = &self.blocks.as_words()[w];
+            let lo = (row + pos) % WORD_BITS;
+            let end = to.min(pos + (WORD_BITS - 1 - lo));
+            let hi = (row + end) % WORD_BITS;
+            let mask = (pattern << (lo % stride.min(WORD_BITS)))
+                & (usize::MAX << lo)
+                & (usize::MAX >> (WORD_BITS - 1 - hi));
+            // with a big stride there's only one candidate
+            let mask = if stride < WORD_BITS { mask } else { 1 << lo };
 
-        let max_order = HUGE_ORDER - calculate_order_for_size(HUGE_ORDER, base_shift, capacity);
-        Self::new_in(max_order, multiplier, Some(capacity), a)
-    }
+            let mut candidates = word.load(Ordering::Relaxed) & mask;
+            while candidates != 0 {
+                let bit = candidates.trailing_zeros() as usize;
+                let mask = 1 << bit;
+                let claimed = match claim {
+                    Claim::No => true,
+                    Claim::Allocation => self.claim_in_word(order, w, mask, Ordering::Acquire),
+                    Claim::Split => self
+                        .claim_to_split(|| self.claim_in_word(order, w, mask, Ordering::Acquire)),
+                };
+                if claimed {
+                    return Some(pos + (bit - lo));
+                }
+                // the block was claimed concurrently, check the remaining ones again
+                candidates = word.load(Ordering::Relaxed) & mask & (usize::MAX << bit);
+            }
 
-    fn calculate_block_size(&self, order: usize) -> usize {
-        calculate_block_size(self.max_order, order)
+            // the first candidate after this word
+            pos += ((end - pos) / stride + 1) * stride;
+        }
+        None
     }
 
-    fn calculate_order_for_size(&self, size: usize) -> usize {
-        calculate_order_for_size(self.max_order, self.base_shift, size)
-    }
+    pub fn allocate_at_with_size(&self, size: usize, idx: usize) -> bool {
+        let order = self.order_for_size(size);
+        let idx = self.offset(idx);
+        self.check_block(idx, order);
 
-    pub fn capacity(&self) -> usize {
-        self.max_idx
-    }
+        if !self.begin_allocation() {
+            return false;
+        }
 
-    pub fn is_unused(&self) -> bool {
-        self.allocations
-            .compare_and_swap(0, isize::min_value(), Ordering::Relaxed)
-            == 0
+        let res = self.allocate_at(order, idx);
+        if res {
+            self.set_allocated(order, idx >> self.base_shift, true);
+            self.add_used(self.calculate_block_size(order) << self.base_shift);
+        } else {
+            self.allocations.fetch_sub(1, Ordering::Relaxed);
+        }
+        res
     }
 
-    /// ```
-    /// use buddy_allocator::Buddies;
+    /// claim the block at `idx`, splitting the free block containing it if necessary
     ///
-    /// let buddies = Buddies::new(5, 4, None);
-    /// for i in 0..=buddies.capacity() {
-    ///     assert!(i <= buddies.real_size_for_allocation(i), "{} -> {}", i, buddies.real_size_for_allocation(i));
-    /// }
-    /// ```
-    pub fn real_size_for_allocation(&self, size: usize) -> usize {
-        let order = self.calculate_order_for_size(size);
-        self.calculate_block_size(order) << self.base_shift
-    }
-
-    pub fn allocate_with_size(&self, size: usize, align: usize) -> Option<usize> {
-        assert!(size <= self.max_idx, "size is too big");
+    /// this checks at most `order + 1` blocks and then splits at most `order` blocks.
+    pub fn allocate_at(&self, order: usize, idx: usize) -> bool {
+        let idx = idx >> self.base_shift;
 
-        let value = self.allocations.fetch_add(1, Ordering::Relaxed);
-        if value < 0 {
-            self.allocations.fetch_sub(1, Ordering::Relaxed);
-            return None;
+        // search the ancestors upwards until a free one is found
+        let mut found_order = order;
+        let mut found_idx = idx;
+        loop {
+            let claimed = if found_order == order {
+                self.claim(found_order, found_idx)
+            } else {
+                // only a split of an ancestor that looks free is announced, otherwise failing
+                // calls would keep the searches of each other going
+                self.is_free(found_order, found_idx, Ordering::Relaxed)
+                    && self.claim_to_split(|| self.claim(found_order, found_idx))
+            };
+            if claimed {
+                break;
+            }
+            if found_order == 0 {
+                return false;
+            }
+            found_idx &= !self.calculate_block_size(found_order);
+            found_order -= 1;
         }
 
-        let order = self.calculate_order_for_size(size);
-        let res = self.allocate(order, align);
-        if res.is_none() {
-            self.allocations.fetch_sub(1, Ordering::Relaxed);
+        // split it down to the requested order, freeing the halves without the block
+        for order in found_order + 1..=order {
+            let block_size = self.calculate_block_size(order);
+            self.set_free(
+                order,
+                (idx & !(block_size - 1)) ^ block_size,
+                Ordering::Release,
+            );
         }
-        res
+        if found_order != order {
+            self.end_reshape();
+        }
+        true
     }
 
-    fn allocate(&self, order: usize, align_size: usize) -> Option<usize> {
-        assert!(align_size <= self.max_idx, "align is too big");
-        assert!(align_size.is_power_of_two(), "align is not a power of two");
-
-        let block_size = self.calculate_block_size(order);
-        let align_block_size = align_size >> self.base_shift;
-        let inc_size = block_size.max(align_block_size);
+    /// find the size of the allocation starting at `idx`
+    ///
+    /// this checks one block per order, so it takes O(max_order) steps
+    pub fn allocation_size_at(&self, idx: usize) -> Option<usize> {
+        let idx = idx.checked_sub(self.base)?;
+        if idx & ((1 << self.base_shift) - 1) != 0 || idx >= self.max_idx {
+            return None;
+        }
 
-        let mut idx = 0;
-        while idx + inc_size <= (self.max_idx >> self.base_shift) {
-            let was_available = self[(order, idx)].compare_and_swap(true, false, Ordering::Relaxed);
-            if was_available {
-                return Some(idx << self.base_shift);
+        let block_idx = idx >> self.base_shift;
+        for order in 0..self.max_order {
+            let block_size = self.calculate_block_size(order);
+            if block_idx & (block_size - 1) != 0 {
+                continue;
+            }
+            if (block_idx + block_size) << self.base_shift > self.max_idx {
+                continue;
             }
-            idx += inc_size;
-        }
 
-        if order != 0 {
-            if let Some(idx) = self.allocate(order - 1, align_size) {
-                self[(order, (idx >> self.base_shift) ^ block_size)].store(true, Ordering::Relaxed);
-                return Some(idx);
+            if self.is_allocated(order, block_idx) {
+                return Some(block_size << self.base_shift);
             }
         }
 
         None
     }
 
-    pub fn allocate_at_with_size(&self, size: usize, idx: usize) -> bool {
-        assert!(size <= self.max_idx, "size is too big");
+    /// returns the index and size of the free block the deallocated block was merged into
+    pub fn deallocate_with_size(&self, idx: usize, size: usize) -> (usize, usize) {
+        self.deallocate_order(idx, self.order_for_size(size))
+    }
+
+    /// returns the index and size of the free block the deallocated block was merged into
+    ///
+    /// with the `quarantine` feature that's the block released in its place, or `(idx, 0)` if
+    /// the quarantine wasn't full
+    pub fn deallocate_order(&self, idx: usize, order: usize) -> (usize, usize) {
+        self.check_order(order);
+        let offset = self.offset(idx);
+        self.check_block(offset, order);
+
+        #[cfg(feature = "checked-dealloc")]
+        self.check_allocated(idx, order, "deallocated");
+
+        // the block stays allocated while it's quarantined, the evicted one is released instead
+        #[cfg(feature = "quarantine")]
+        assert!(
+            !self.is_poisoned(),
+            "deallocation after is_unused returned true"
+        );
+        #[cfg(feature = "quarantine")]
+        let (offset, order) = match self.quarantine(offset, order) {
+            Some(evicted) => evicted,
+            None => return (idx, 0),
+        };
 
-        let order = self.calculate_order_for_size(size);
-        self.allocate_at(order, idx)
+        self.release(offset, order)
     }
 
-    pub fn allocate_at(&self, order: usize, idx: usize) -> bool {
-        let was_available =
-            self[(order, idx >> self.base_shift)].compare_and_swap(true, false, Ordering::Relaxed);
-        if was_available {
-            return true;
-        }
+    /// stop counting the allocated block at `offset` and free it, returns the index and size of
+    /// the free block it was merged into
+    fn release(&self, offset: usize, order: usize) -> (usize, usize) {
+        self.end_allocation();
+        self.set_allocated(order, offset >> self.base_shift, false);
+        self.sub_used(self.calculate_block_size(order) << self.base_shift);
+        let (offset, order) = self.deallocate(offset, order);
+        (
+            self.base + offset,
+            self.calculate_block_size(order) << self.base_shift,
+        )
+    }
 
-        if order != 0 {
-            let block_size = self.calculate_block_size(order) << self.base_shift;
-            if self.allocate_at(order - 1, idx & !block_size) {
-                self[(order, (idx ^ block_size) >> self.base_shift)].store(true, Ordering::Relaxed);
-                return true;
+    /// call the decommit handler for the block of `order` at `idx` if it's big enough
+    ///
+    /// the block has to be claimed, so it can't be allocated before the handler returns
+    fn decommit(&self, order: usize, idx: usize) {
+        if let Some((handler, max_order)) = self.decommit {
+            if order <= max_order {
+                handler(
+                    self.base + (idx << self.base_shift),
+                    self.calculate_block_size(order) << self.base_shift,
+                );
             }
         }
+    }
 
-        false
+    /// call `handler` with the index and size of the free block a deallocation ended up in if
+    /// its order is at most `max_order`, before the block is marked as free
+    pub fn set_decommit_handler(&mut self, handler: DecommitHandler, max_order: usize) {
+        self.check_order(max_order);
+        self.decommit = Some((handler, max_order));
     }
 
-    pub fn deallocate_with_size(&self, idx: usize, size: usize) {
-        self.allocations.fetch_sub(1, Ordering::Relaxed);
-        let order = self.calculate_order_for_size(size);
-        self.deallocate(idx, order)
+    /// panic unless a block of `order` was allocated at `idx`
+    ///
+    /// this uses the allocated bits, so unlike the free bits it can tell a block that was freed
+    /// and merged with its buddy from an allocated one.
+    #[cfg(feature = "checked-dealloc")]
+    fn check_allocated(&self, idx: usize, order: usize, action: &str) {
+        let offset = self.offset(idx);
+        let allocated = offset & ((1 << self.base_shift) - 1) == 0
+            && offset < self.max_idx
+            && offset & ((self.calculate_block_size(order) << self.base_shift) - 1) == 0
+            && self.is_allocated(order, offset >> self.base_shift);
+        if !allocated {
+            match self.allocation_size_at(idx) {
+                Some(expected) => panic!(
+                    "{} was {} with size {} but allocated with size {}",
+                    idx,
+                    action,
+                    self.size_for_order(order),
+                    expected
+                ),
+                None => panic!(
+                    "{} with size {} is not allocated",
+                    idx,
+                    self.size_for_order(order)
+                ),
+            }
+        }
     }
 
-    fn deallocate(&self, orig_idx: usize, order: usize) {
+    /// free a block and merge it with its buddy if possible, returns the offset and order of the
+    /// free block it ended up in
+    ///
+    /// ```
+    /// use buddy_allocator::Buddies;
+    ///
+    /// let mut seed = 0x2545_f491_u32;
+    /// for &multiplier in &[1, 2, 4] {
+    ///     for capacity in (multiplier..=64).step_by(multiplier) {
+    ///         let buddies = Buddies::with_capacity(capacity, multiplier);
+    ///         let mut initial = [0; 64];
+    ///         let len = buddies.export_state(&mut initial).unwrap();
+    ///
+    ///         let mut indices = [0; 64];
+    ///         let count = capacity / multiplier;
+    ///         for idx in indices[..count].iter_mut() {
+    ///             *idx = buddies.allocate(multiplier, 1).unwrap();
+    ///         }
+    ///         assert_eq!(buddies.allocate(multiplier, 1), None);
+    ///
+    ///         // free in a pseudo random order
+    ///         for i in (1..count).rev() {
+    ///             seed ^= seed << 13;
+    ///             seed ^= seed >> 17;
+    ///             seed ^= seed << 5;
+    ///             indices.swap(i, seed as usize % (i + 1));
+    ///         }
+    ///         for &idx in indices[..count].iter() {
+    ///             buddies.deallocate(idx, multiplier);
+    ///             # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///         }
+    ///
+    ///         let mut state = [0; 64];
+    ///         buddies.export_state(&mut state).unwrap();
+    ///         assert_eq!(&state[..len], &initial[..len], "capacity {} multiplier {}", capacity, multiplier);
+    ///         assert!(buddies.is_unused());
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// concurrent allocations never overlap and concurrent deallocations don't miss merges
+    /// ```
+    /// use buddy_allocator::Buddies;
+    /// use std::{
+    ///     sync::{
+    ///         atomic::{AtomicBool, Ordering},
+    ///         Arc,
+    ///     },
+    ///     thread,
+    /// };
+    ///
+    /// const CAPACITY: usize = 256;
+    ///
+    /// let buddies = Arc::new(Buddies::new(9, 1, None));
+    /// let mut initial = [0; 155];
+    /// buddies.export_state(&mut initial).unwrap();
+    /// let owned = Arc::new((0..CAPACITY).map(|_| AtomicBool::new(false)).collect::<Vec<_>>());
+    ///
+    /// let threads = (0..8u32)
+    ///     .map(|i| {
+    ///         let buddies = buddies.clone();
+    ///         let owned = owned.clone();
+    ///         thread::spawn(move || {
+    ///             let mut seed = 0x9e37_79b9 ^ i;
+    ///             let mut live = Vec::new();
+    ///             for _ in 0..5000 {
+    ///                 seed ^= seed << 13;
+    ///                 seed ^= seed >> 17;
+    ///                 seed ^= seed << 5;
+    ///                 if live.len() < 4 && seed % 3 != 0 {
+    ///                     let order = 5 + seed as usize % 4;
+    ///                     if let Some(idx) = buddies.allocate_order(order, 1) {
+    ///                         let size = buddies.size_for_order(order);
+    ///                         for unit in &owned[idx..idx + size] {
+    ///                             assert!(!unit.swap(true, Ordering::SeqCst), "{} overlaps", idx);
+    ///                         }
+    ///                         live.push((idx, order));
+    ///                     }
+    ///                 } else if let Some((idx, order)) = live.pop() {
+    ///                     let size = buddies.size_for_order(order);
+    ///                     for unit in &owned[idx..idx + size] {
+    ///                         unit.store(false, Ordering::SeqCst);
+    ///                     }
+    ///                     buddies.deallocate_order(idx, order);
+    ///                     # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///                 }
+    ///             }
+    ///             for (idx, order) in live {
+    ///                 let size = buddies.size_for_order(order);
+    ///                 for unit in &owned[idx..idx + size] {
+    ///                     unit.store(false, Ordering::SeqCst);
+    ///                 }
+    ///                 buddies.deallocate_order(idx, order);
+    ///                 # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+    ///             }
+    ///         })
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// for thread in threads {
+    ///     thread.join().unwrap();
+    /// }
+    ///
+    /// let mut state = [0; 155];
+    /// buddies.export_state(&mut state).unwrap();
+    /// assert_eq!(&state[..], &initial[..]);
+    /// ```
+    fn deallocate(&self, orig_idx: usize, order: usize) -> (usize, usize) {
         assert_eq!(
             orig_idx & ((1 << self.base_shift) - 1),
             0,
             "alignment is off"
         );
 
-        let idx = orig_idx >> self.base_shift;
-        let block_size = self.calculate_block_size(order);
+        let mut idx = orig_idx >> self.base_shift;
+        let mut order = order;
 
         assert!(
-            !self[(order, idx)].load(Ordering::Relaxed),
+            !self.is_free(order, idx, Ordering::Relaxed),
             "{} at order {} is not allocated",
             orig_idx,
             order
         );
 
-        if order != 0 && ((idx ^ block_size) + block_size) << self.base_shift < self.max_idx {
+        // the merges hide the buddies they claim, see [RawBuddies::begin_reshape]
+        self.begin_reshape();
+
+        // every iteration merges the block with its buddy and continues with the parent, so
+        // there are at most `order + 1` iterations
+        loop {
+            let block_size = self.calculate_block_size(order);
+            let can_merge =
+                order != 0 && ((idx ^ block_size) + block_size) << self.base_shift <= self.max_idx;
+
             // try to join with the buddy
-            let was_available =
-                self[(order, idx ^ block_size)].compare_and_swap(true, false, Ordering::Relaxed);
-            if was_available {
-                self.deallocate((idx & !block_size) << self.base_shift, order - 1);
-                return;
+            let merged = can_merge && self.claim(order, idx ^ block_size) || {
+                // the block is still claimed, so the handler can't race with its next owner
+                self.decommit(order, idx);
+
+                // mark as available, this is also a release
+                self.set_free(order, idx, Ordering::SeqCst);
+
+                // the buddy might have been deallocated concurrently after the check above,
+                // but before this block was marked as available. in that case neither
+                // deallocation saw the other block, so one of them has to merge afterwards.
+                can_merge && self.merge_free_buddies(order, idx & !block_size)
+            };
+            if !merged {
+                self.end_reshape();
+                // this block was free when it was marked as available, even if it's claimed
+                // again right away
+                return (idx << self.base_shift, order);
+            }
+
+            idx &= !block_size;
+            order -= 1;
+        }
+    }
+
+    /// claim the block at `left_idx` and its buddy if both are free
+    ///
+    /// returns true if both were claimed, their parent then has to be freed. the left block is
+    /// always claimed first, so only one thread can claim both of them. the stores marking the
+    /// blocks as available and the loads here are sequentially consistent, so at least one of
+    /// two concurrent deallocations sees both blocks as free.
+    fn merge_free_buddies(&self, order: usize, left_idx: usize) -> bool {
+        let block_size = self.calculate_block_size(order);
+        let right_idx = left_idx | block_size;
+        loop {
+            if !self.is_free(order, left_idx, Ordering::SeqCst)
+                || !self.is_free(order, right_idx, Ordering::SeqCst)
+            {
+                return false;
+            }
+
+            if !self.claim_ordered(order, left_idx, Ordering::SeqCst) {
+                // the block was allocated or claimed by another merge
+                return false;
+            }
+            if self.claim_ordered(order, right_idx, Ordering::SeqCst) {
+                return true;
+            }
+
+            // the right block was allocated in the meantime. it might have been deallocated
+            // again while the left block was claimed, so check again after releasing it.
+            self.set_free(order, left_idx, Ordering::SeqCst);
+        }
+    }
+
+    /// turn the allocated block of `size` at `idx` into two allocated halves
+    ///
+    /// the halves are only marked as allocated, the free bits don't change.
+    pub fn split_with_size(&self, idx: usize, size: usize) -> (usize, usize) {
+        assert!(
+            self.is_exact_size(size),
+            "size {} is not the size of a block",
+            size
+        );
+        let order = self.order_for_size(size);
+        assert!(
+            order + 1 < self.max_order,
+            "{} with size {} has the smallest size and can't be split",
+            idx,
+            size
+        );
+        let offset = self.offset(idx);
+        self.check_block(offset, order);
+        assert!(
+            self.is_allocated(order, offset >> self.base_shift),
+            "{} with size {} is not allocated",
+            idx,
+            size
+        );
+        assert!(self.begin_allocation(), "the instance is poisoned");
+
+        let block_idx = offset >> self.base_shift;
+        let half = size / 2;
+        self.set_allocated(order, block_idx, false);
+        self.set_allocated(order + 1, block_idx, true);
+        self.set_allocated(order + 1, block_idx + (half >> self.base_shift), true);
+        (idx, idx + half)
+    }
+
+    /// turn the allocated buddies of `size` at `left_idx` and `right_idx` into one allocation
+    ///
+    /// only the allocated bits change, the free bits stay the same.
+    pub fn merge_with_size(
+        &self,
+        left_idx: usize,
+        right_idx: usize,
+        size: usize,
+    ) -> Result<usize, MergeError> {
+        // the merged block has to be a block of the tree
+        let offset = left_idx.wrapping_sub(self.base);
+        let is_block = self.is_exact_size(size)
+            && left_idx >= self.base
+            && size
+                .checked_mul(2)
+                .filter(|&merged| offset & (merged - 1) == 0)
+                .and_then(|merged| offset.checked_add(merged))
+                .is_some_and(|end| end <= self.max_idx);
+        if !is_block || right_idx != left_idx + size {
+            return Err(MergeError::NotBuddies);
+        }
+        for &idx in &[left_idx, right_idx] {
+            match self.allocation_size_at(idx) {
+                Some(allocated) if allocated == size => {}
+                Some(allocated) => {
+                    return Err(MergeError::SizeMismatch {
+                        idx,
+                        size: allocated,
+                    })
+                }
+                None => return Err(MergeError::NotAllocated { idx }),
             }
         }
 
-        // mark as available
-        self[(order, idx)].store(true, Ordering::Relaxed);
+        let order = self.order_for_size(size);
+        let block_idx = offset >> self.base_shift;
+        self.set_allocated(order, block_idx, false);
+        self.set_allocated(order, block_idx + (size >> self.base_shift), false);
+        self.set_allocated(order - 1, block_idx, true);
+        self.end_allocation();
+        Ok(left_idx)
     }
 
     pub fn shrink_with_size(&self, idx: usize, old_size: usize, new_size: usize) {
-        let old_order = self.calculate_order_for_size(old_size);
-        let new_order = self.calculate_order_for_size(new_size);
-        self.shrink(idx, old_order, new_order)
+        assert_ne!(
+            new_size, 0,
+            "can't shrink to size 0, deallocate the block instead"
+        );
+        let old_order = self.order_for_size(old_size);
+        let new_order = self.order_for_size(new_size);
+        let offset = self.offset(idx);
+        self.check_block(offset, old_order);
+        #[cfg(feature = "debug-integrity")]
+        self.check_allocated(idx, old_order, "shrunk");
+        self.shrink(offset, old_order, new_order);
+        self.set_allocated(old_order, offset >> self.base_shift, false);
+        self.set_allocated(new_order, offset >> self.base_shift, true);
+        self.sub_used(
+            (self.calculate_block_size(old_order) - self.calculate_block_size(new_order))
+                << self.base_shift,
+        );
+    }
+
+    /// shrink a block, with `MayMove` the allocation moves to a free block of the new size
+    /// aligned to `align` if there is one and the old block is released. returns the new index
+    pub fn shrink_or_move_with_size(
+        &self,
+        idx: usize,
+        old_size: usize,
+        new_size: usize,
+        align: usize,
+        placement: ReallocPlacement,
+    ) -> usize {
+        assert_ne!(
+            new_size, 0,
+            "can't shrink to size 0, deallocate the block instead"
+        );
+        let new_order = self.order_for_size(new_size);
+        self.check_block(self.offset(idx), self.order_for_size(old_size));
+        if let ReallocPlacement::MayMove = placement {
+            // moving within the same order doesn't free anything
+            if new_order > self.order_for_size(old_size) {
+                if let Some(new_idx) = self.allocate_without_splitting(new_order, align) {
+                    self.deallocate_with_size(idx, old_size);
+                    return new_idx;
+                }
+            }
+        }
+        self.shrink_with_size(idx, old_size, new_size);
+        idx
+    }
+
+    /// allocate a free block of `order` aligned to `align` without splitting a bigger one
+    pub fn allocate_without_splitting(&self, order: usize, align: usize) -> Option<usize> {
+        assert!(align <= self.max_idx, "align is too big");
+        assert!(align.is_power_of_two(), "align is not a power of two");
+
+        let block_size = self.calculate_block_size(order);
+        let align_offset = (self.base.wrapping_neg() & (align - 1)) >> self.base_shift;
+        if align_offset & (block_size - 1) != 0 || !self.may_have_free(order) {
+            return None;
+        }
+        if !self.begin_allocation() {
+            return None;
+        }
+
+        let inc_size = block_size.max(align >> self.base_shift);
+        match self.search(|| self.scan(order, align_offset, inc_size, Claim::Allocation)) {
+            Some(idx) => {
+                self.set_allocated(order, idx, true);
+                self.add_used(block_size << self.base_shift);
+                Some(self.base + (idx << self.base_shift))
+            }
+            None => {
+                self.allocations.fetch_sub(1, Ordering::Relaxed);
+                None
+            }
+        }
     }
 
     fn shrink(&self, orig_idx: usize, old_order: usize, new_order: usize) {
@@ -267,7 +2612,7 @@ impl<A: AllocRef> RawBuddies<A> {
         let mut block_size = self.calculate_block_size(old_order);
 
         assert!(
-            !self[(old_order, idx)].load(Ordering::Relaxed),
+            !self.is_free(old_order, idx, Ordering::Relaxed),
             "{} at order {} is not allocated",
             orig_idx,
             old_order
@@ -276,7 +2621,7 @@ impl<A: AllocRef> RawBuddies<A> {
         let order_diff = new_order - old_order;
         for i in 1..=order_diff {
             block_size >>= 1;
-            self[(old_order + i, idx ^ block_size)].store(true, Ordering::Relaxed);
+            self.set_free(old_order + i, idx ^ block_size, Ordering::Release);
         }
     }
 
@@ -287,11 +2632,98 @@ impl<A: AllocRef> RawBuddies<A> {
         new_size: usize,
         placement: ReallocPlacement,
     ) -> Option<usize> {
-        let old_order = self.calculate_order_for_size(old_size);
-        let new_order = self.calculate_order_for_size(new_size);
-        self.grow(idx, old_order, new_order, placement)
+        let old_order = self.order_for_size(old_size);
+        let new_order = self.order_for_size(new_size);
+        let offset = self.offset(idx);
+        self.check_block(offset, old_order);
+        #[cfg(feature = "debug-integrity")]
+        self.check_allocated(idx, old_order, "grown");
+        let new_idx = self.grow(offset, old_order, new_order, placement)?;
+        self.set_allocated(old_order, offset >> self.base_shift, false);
+        self.set_allocated(new_order, new_idx >> self.base_shift, true);
+        self.add_used(
+            (self.calculate_block_size(new_order) - self.calculate_block_size(old_order))
+                << self.base_shift,
+        );
+        Some(self.base + new_idx)
+    }
+
+    /// grow a block by merging it with its buddies, with `MayMove` the allocation moves to a new
+    /// block if that fails
+    pub fn grow_or_move_with_size(
+        &self,
+        idx: usize,
+        old_size: usize,
+        new_size: usize,
+        placement: ReallocPlacement,
+    ) -> Option<usize> {
+        self.grow_with_size(idx, old_size, new_size, placement)
+            .or_else(|| match placement {
+                ReallocPlacement::InPlace => None,
+                ReallocPlacement::MayMove => self.move_with_size(idx, old_size, new_size),
+            })
+    }
+
+    /// move an allocation into a new block for `new_size` and release the old block
+    ///
+    /// the new block keeps every alignment the old index had, an index of 0 counts as aligned
+    /// to the biggest block.
+    fn move_with_size(&self, idx: usize, old_size: usize, new_size: usize) -> Option<usize> {
+        let new_order = self.order_for_size(new_size);
+        let lowest_bit = idx & idx.wrapping_neg();
+        let align = match lowest_bit {
+            0 => self.max_allocation_size(),
+            _ => lowest_bit.min(self.max_allocation_size()),
+        };
+        let new_idx = self.allocate_order(new_order, align, Some(idx))?;
+        self.deallocate_with_size(idx, old_size);
+        Some(new_idx)
     }
 
+    /// grow a block by merging it with its buddies
+    ///
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::ReallocPlacement;
+    /// use buddy_allocator::Buddies;
+    ///
+    /// for idx in 0..8 {
+    ///     for &new_size in &[2, 4, 8] {
+    ///         let buddies = Buddies::new(4, 1, None);
+    ///         assert!(buddies.allocate_at(1, idx));
+    ///         let res = buddies.grow(idx, 1, new_size, ReallocPlacement::InPlace);
+    ///         if idx % new_size == 0 {
+    ///             assert_eq!(res, Some(idx));
+    ///         } else {
+    ///             assert_eq!(res, None);
+    ///             assert_eq!(buddies.allocation_size_at(idx), Some(1));
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// a failed grow leaves the blocks untouched
+    /// ```
+    /// #![feature(allocator_api)]
+    /// use alloc_wg::alloc::ReallocPlacement;
+    /// use buddy_allocator::Buddies;
+    ///
+    /// for &placement in &[ReallocPlacement::InPlace, ReallocPlacement::MayMove] {
+    ///     // block the merge with the buddy of size 1, 2, 4 and 8
+    ///     for &blocker in &[1, 2, 4, 8] {
+    ///         let buddies = Buddies::new(5, 1, None);
+    ///         assert!(buddies.allocate_at(1, 0));
+    ///         assert!(buddies.allocate_at(blocker, blocker));
+    ///
+    ///         let mut before = [0; 35];
+    ///         buddies.export_state(&mut before).unwrap();
+    ///         assert_eq!(buddies.grow(0, 1, 16, placement), None);
+    ///         let mut after = [0; 35];
+    ///         buddies.export_state(&mut after).unwrap();
+    ///         assert_eq!(before, after);
+    ///     }
+    /// }
+    /// ```
     fn grow(
         &self,
         orig_idx: usize,
@@ -309,7 +2741,7 @@ impl<A: AllocRef> RawBuddies<A> {
         let new_block_size = self.calculate_block_size(new_order);
 
         assert!(
-            !self[(old_order, idx)].load(Ordering::Relaxed),
+            !self.is_free(old_order, idx, Ordering::Relaxed),
             "{} at order {} is not allocated",
             orig_idx,
             old_order
@@ -322,72 +2754,166 @@ impl<A: AllocRef> RawBuddies<A> {
         }
 
         if let ReallocPlacement::InPlace = placement {
-            // check if block is already perfectly aligned
-            if idx & new_block_size != 0 {
+            // the grown block starts at the same index only if it's aligned to the new size
+            if idx & (new_block_size - 1) != 0 {
                 return None; // fail allocation
             }
         }
 
+        // the buddies are freed again if a later one can't be claimed, so they're hidden until
+        // then if there's more than one
+        let reshape = order_diff > 1;
+        if reshape {
+            self.begin_reshape();
+        }
+
+        // the indices of the claimed buddies, the buddy claimed in step `i` has the order
+        // `old_order - i`
+        let mut claimed = [0; core::mem::size_of::<usize>() * 8];
         for i in 0..order_diff {
             // try to join with the buddy
             let buddy_idx = (idx ^ block_size) & !(block_size - 1);
             let end = buddy_idx + block_size;
-            let was_available = if end << self.base_shift <= self.max_idx {
-                self[(old_order - i, buddy_idx)].compare_and_swap(true, false, Ordering::Relaxed)
-            } else {
-                false
-            };
+            let was_available =
+                end << self.base_shift <= self.max_idx && self.claim(old_order - i, buddy_idx);
 
             if !was_available {
                 // revert all changes
-                for i in (0..i).rev() {
-                    block_size >>= 1;
-                    self[(old_order - i, (idx ^ block_size) & !(block_size - 1))]
-                        .store(true, Ordering::Relaxed);
+                for (j, &buddy_idx) in claimed[..i].iter().enumerate().rev() {
+                    self.set_free(old_order - j, buddy_idx, Ordering::Release);
+                }
+                if reshape {
+                    self.end_reshape();
                 }
                 return None; // fail allocation
             }
+            claimed[i] = buddy_idx;
 
             block_size <<= 1;
         }
+        if reshape {
+            // the buddies are allocated now, so a search missing them didn't miss a free block
+            self.cancel_reshape();
+        }
 
-        Some((idx & !(new_block_size - 1)) << self.base_shift)
+        let new_idx = (idx & !(new_block_size - 1)) << self.base_shift;
+        if let ReallocPlacement::InPlace = placement {
+            debug_assert_eq!(new_idx, orig_idx, "in place grow moved the block");
+        }
+        Some(new_idx)
     }
-}
 
-impl<A: AllocRef> Index<(usize, usize)> for RawBuddies<A> {
-    type Output = AtomicBool;
+    /// check that the blocks form a valid buddy tree and the counters match the blocks
+    ///
+    /// this is only meaningful if there are no concurrent modifications
+    pub fn check_integrity(&self) -> Result<(), IntegrityError> {
+        let max_blocks = self.max_idx >> self.base_shift;
+        let mut free = 0;
+        let mut allocated = 0;
+        let mut allocations = 0;
 
-    fn index(&self, (order, idx): (usize, usize)) -> &AtomicBool {
-        let block_size = self.calculate_block_size(order);
-        debug_assert_eq!(
-            idx & (block_size - 1),
-            0,
-            "trying to access child {} at order {} (alignment is off)",
-            idx,
-            order,
-        );
-        debug_assert!(
-            self.max_order >= order,
-            "order {} is too big for max order {}",
-            order,
-            self.max_order
-        );
-        debug_assert!(
-            idx < (self.max_idx >> self.base_shift),
-            "idx {} is greater or equal to max_idx {}",
-            (idx << self.base_shift),
-            self.max_idx
-        );
+        let mut i = 0;
+        for order in 0..self.max_order {
+            let block_size = self.calculate_block_size(order);
+            let mut free_blocks = 0;
+            for pos in 0..self.row_len(order) {
+                let is_available = self.test(Bit::Available, i, Ordering::Relaxed);
+                let is_allocated = self.test(Bit::Allocated, i, Ordering::Relaxed);
+                i += 1;
+                if !is_available && !is_allocated {
+                    continue;
+                }
+
+                let idx = pos * block_size;
+                let orig_idx = self.base + (idx << self.base_shift);
+                if is_available {
+                    free_blocks += 1;
+                    if SUMMARY {
+                        let (summary, mask) = self.summary((i - 1) / WORD_BITS);
+                        if summary.load(Ordering::Relaxed) & mask == 0 {
+                            return Err(IntegrityError::Unsummarized {
+                                order,
+                                idx: orig_idx,
+                            });
+                        }
+                    }
+                }
+                if is_available && is_allocated {
+                    return Err(IntegrityError::FreeAndAllocated {
+                        order,
+                        idx: orig_idx,
+                    });
+                }
+                if idx + block_size > max_blocks {
+                    return Err(IntegrityError::OutOfBounds {
+                        order,
+                        idx: orig_idx,
+                    });
+                }
+
+                for ancestor_order in 0..order {
+                    let ancestor_block_size = self.calculate_block_size(ancestor_order);
+                    let ancestor_idx = idx & !(ancestor_block_size - 1);
+                    if self.is_free(ancestor_order, ancestor_idx, Ordering::Relaxed)
+                        || self.is_allocated(ancestor_order, ancestor_idx)
+                    {
+                        return Err(IntegrityError::Overlap {
+                            order,
+                            idx: orig_idx,
+                        });
+                    }
+                }
+
+                if is_available {
+                    free += block_size;
+                } else {
+                    allocated += block_size;
+                    allocations += 1;
+                }
+            }
+
+            let counter = widen(self.free[order].load(Ordering::Relaxed));
+            if counter != free_blocks {
+                return Err(IntegrityError::FreeCount {
+                    order,
+                    counter,
+                    free: free_blocks,
+                });
+            }
+        }
+
+        if free + allocated != max_blocks {
+            return Err(IntegrityError::Lost {
+                free: free << self.base_shift,
+                allocated: allocated << self.base_shift,
+            });
+        }
+
+        let counter = self.allocations.load(Ordering::Relaxed);
+        // a poisoned instance has no allocations
+        let expected = if counter == isize::MIN && allocations == 0 {
+            isize::MIN
+        } else {
+            allocations as isize
+        };
+        if counter != expected {
+            return Err(IntegrityError::Allocations {
+                counter,
+                allocated: allocations,
+            });
+        }
 
-        let mut blocks = 0;
-        let mut last_blocks = 1;
-        for _ in 0..order {
-            blocks += last_blocks;
-            last_blocks <<= 1;
+        #[cfg(feature = "stats")]
+        {
+            let counter = self.used.load(Ordering::Relaxed);
+            if counter != allocated << self.base_shift {
+                return Err(IntegrityError::Used {
+                    counter,
+                    allocated: allocated << self.base_shift,
+                });
+            }
         }
 
-        let i = blocks + (idx >> (self.max_order - order - 1));
-        &self.blocks[i]
+        Ok(())
     }
 }
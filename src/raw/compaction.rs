@@ -0,0 +1,158 @@
+//! planning which allocations to move to free a block
+
+use super::{RawBuddies, Storage};
+use crate::sync::{Backend, Ordering};
+use alloc_wg::vec::Vec;
+
+/// a move of an allocation suggested by a [CompactionPlan](CompactionPlan)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionMove {
+    /// the index of the allocation
+    pub from: usize,
+    /// the size of the allocated buddy
+    pub size: usize,
+    /// the index to move the allocation to, it is free when the plan is made
+    pub to: usize,
+}
+
+/// moves that free a block, returned by [Buddies::plan_compaction](crate::Buddies::plan_compaction)
+///
+/// the plan is executed by moving every allocation: allocate the destination with
+/// [Buddies::allocate_at](crate::Buddies::allocate_at), move the data and deallocate the old
+/// buddy. afterwards the block at [CompactionPlan::idx](CompactionPlan::idx) is free.
+#[derive(Debug)]
+pub struct CompactionPlan {
+    idx: usize,
+    moves: Vec<CompactionMove>,
+}
+
+impl CompactionPlan {
+    /// the index of the block that is free after the moves
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
+    /// the moves, ordered from the biggest allocation to the smallest one
+    pub fn moves(&self) -> &[CompactionMove] {
+        &self.moves
+    }
+}
+
+impl<B: Backend, S: Storage<B::Usize>> RawBuddies<S, B> {
+    /// plan the moves that free a block of `order`
+    ///
+    /// the blocks needing the fewest moves are tried first, ties are broken by the bytes moved.
+    /// this is only meaningful if there are no concurrent modifications.
+    pub fn plan_compaction(&self, order: usize) -> Option<CompactionPlan> {
+        let block_size = self.calculate_block_size(order);
+        let max_blocks = self.max_idx >> self.base_shift;
+
+        // (moves, moved units, block) of every block that can be freed by moving its contents
+        let mut candidates = Vec::new();
+        for block in (0..max_blocks / block_size).map(|i| i * block_size) {
+            let in_allocation = (0..order).any(|ancestor_order| {
+                let ancestor_size = self.calculate_block_size(ancestor_order);
+                let ancestor = block & !(ancestor_size - 1);
+                ancestor + ancestor_size <= max_blocks
+                    && self.is_allocated(ancestor_order, ancestor)
+            });
+            if in_allocation {
+                continue;
+            }
+
+            let mut moves = 0;
+            let mut units = 0;
+            self.for_each_allocation_in(order, block, |_, size| {
+                moves += 1;
+                units += size;
+            });
+            candidates.push((moves, units, block));
+        }
+        candidates.sort_unstable();
+
+        candidates
+            .iter()
+            .find_map(|&(_, _, block)| self.plan_moves(order, block))
+    }
+
+    /// call `f` with the position and size (in units) of every allocated block within the block
+    /// of `order` at `block`
+    fn for_each_allocation_in(&self, order: usize, block: usize, mut f: impl FnMut(usize, usize)) {
+        for sub_order in order..self.max_order {
+            let sub_size = self.calculate_block_size(sub_order);
+            let max_blocks = self.max_idx >> self.base_shift;
+            let end = (block + self.calculate_block_size(order)).min(max_blocks);
+            let mut pos = block;
+            while pos + sub_size <= end {
+                if self.is_allocated(sub_order, pos) {
+                    f(pos, sub_size);
+                }
+                pos += sub_size;
+            }
+        }
+    }
+
+    /// find free destinations outside of `block` for all allocations in it
+    fn plan_moves(&self, order: usize, block: usize) -> Option<CompactionPlan> {
+        let block_size = self.calculate_block_size(order);
+        let mut allocations = Vec::new();
+        self.for_each_allocation_in(order, block, |pos, size| allocations.push((size, pos)));
+        // place the biggest allocations first, they are the hardest to fit
+        allocations.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut moves: Vec<CompactionMove> = Vec::new();
+        for &(size, from) in allocations.iter() {
+            let to = self.find_destination(size, block, block_size, &moves)?;
+            moves.push(CompactionMove {
+                from: self.base + (from << self.base_shift),
+                size: size << self.base_shift,
+                to: self.base + (to << self.base_shift),
+            });
+        }
+
+        Some(CompactionPlan {
+            idx: self.base + (block << self.base_shift),
+            moves,
+        })
+    }
+
+    /// the lowest free position for a block of `size` units outside of the excluded block and
+    /// the destinations of `moves`
+    fn find_destination(
+        &self,
+        size: usize,
+        excluded: usize,
+        excluded_size: usize,
+        moves: &[CompactionMove],
+    ) -> Option<usize> {
+        let order = self.max_order - size.trailing_zeros() as usize - 1;
+        let max_blocks = self.max_idx >> self.base_shift;
+        let overlaps =
+            |start: usize, len: usize, pos: usize| pos < start + len && start < pos + size;
+
+        let mut pos = 0;
+        while pos + size <= max_blocks {
+            let taken = overlaps(excluded, excluded_size, pos)
+                || moves.iter().any(|m| {
+                    let to = (m.to - self.base) >> self.base_shift;
+                    overlaps(to, m.size >> self.base_shift, pos)
+                });
+            if !taken && self.is_in_free_block(order, pos) {
+                return Some(pos);
+            }
+            pos += size;
+        }
+        None
+    }
+
+    /// whether the block of `order` at `pos` is free or part of a bigger free block
+    fn is_in_free_block(&self, order: usize, pos: usize) -> bool {
+        let max_blocks = self.max_idx >> self.base_shift;
+        (0..=order).any(|ancestor_order| {
+            let ancestor_size = self.calculate_block_size(ancestor_order);
+            let ancestor = pos & !(ancestor_size - 1);
+            ancestor + ancestor_size <= max_blocks
+                && self.is_free(ancestor_order, ancestor, Ordering::Relaxed)
+        })
+    }
+}
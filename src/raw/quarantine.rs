@@ -0,0 +1,59 @@
+//! delaying the reuse of deallocated blocks
+
+use super::{RawBuddies, Storage};
+use crate::sync::{Atomic, AtomicInt, Backend, Ordering};
+
+/// the number of deallocated blocks that are held back
+pub(super) const QUARANTINE_LEN: usize = 16;
+
+/// a ring of deallocated blocks that are still marked as allocated
+///
+/// every slot holds `offset * WORD_BITS + order + 1` of a block with its offset in units, zero
+/// marks an empty slot. a deallocation takes the next slot and releases the block it replaces,
+/// so the blocks are released in the order they were quarantined.
+pub(super) struct Quarantine<B: Backend> {
+    pub(super) next: B::Usize,
+    pub(super) slots: [B::Usize; QUARANTINE_LEN],
+}
+
+impl<B: Backend, S: Storage<B::Usize>> RawBuddies<S, B> {
+    fn quarantine_entry(&self, offset: usize, order: usize) -> usize {
+        (offset >> self.base_shift) * super::WORD_BITS + order + 1
+    }
+
+    /// put a deallocated block into the quarantine, returns the block that has to be released
+    /// in its place as `(offset, order)`
+    ///
+    /// # Panics
+    /// panics if the block is already quarantined
+    pub(super) fn quarantine(&self, offset: usize, order: usize) -> Option<(usize, usize)> {
+        let entry = self.quarantine_entry(offset, order);
+        let quarantined = self
+            .quarantine
+            .slots
+            .iter()
+            .any(|slot| slot.load(Ordering::Relaxed) == entry);
+        assert!(!quarantined, "{} was deallocated twice", self.base + offset);
+
+        let i = self.quarantine.next.fetch_add(1, Ordering::Relaxed) % QUARANTINE_LEN;
+        let evicted = self.quarantine.slots[i].swap(entry, Ordering::AcqRel);
+        self.decode_entry(evicted)
+    }
+
+    fn decode_entry(&self, entry: usize) -> Option<(usize, usize)> {
+        let entry = entry.checked_sub(1)?;
+        Some((
+            (entry / super::WORD_BITS) << self.base_shift,
+            entry % super::WORD_BITS,
+        ))
+    }
+
+    /// release all quarantined blocks
+    pub fn flush_quarantine(&self) {
+        for slot in self.quarantine.slots.iter() {
+            if let Some((offset, order)) = self.decode_entry(slot.swap(0, Ordering::AcqRel)) {
+                self.release(offset, order);
+            }
+        }
+    }
+}
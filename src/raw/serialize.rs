@@ -0,0 +1,326 @@
+//! serde support for [Buddies](crate::Buddies)
+//!
+//! the blocks are serialized as two bit vectors (free and allocated) packed 8 blocks per byte in
+//! the order they are stored in.
+//!
+//! ```
+//! use buddy_allocator::Buddies;
+//!
+//! let buddies = Buddies::with_capacity(48, 4);
+//! let idx1 = buddies.allocate(4, 1).unwrap();
+//! let idx2 = buddies.allocate(16, 1).unwrap();
+//!
+//! let json = serde_json::to_string(&buddies).unwrap();
+//! let copy: Buddies = serde_json::from_str(&json).unwrap();
+//! assert_eq!(copy.num_allocations(), 2);
+//! assert_eq!(copy.allocation_size_at(idx2), Some(16));
+//! assert_eq!(copy.allocate(4, 1), buddies.allocate(4, 1));
+//! assert_eq!(copy.allocate(16, 1), buddies.allocate(16, 1));
+//! assert_eq!(copy.allocate(16, 1), buddies.allocate(16, 1));
+//!
+//! # let _ = idx1;
+//!
+//! // mark both the root and its first child as free
+//! let json = serde_json::to_string(&Buddies::new(3, 1, None)).unwrap();
+//! let json = json.replace("\"available\":[1]", "\"available\":[3]");
+//! assert!(serde_json::from_str::<Buddies>(&json).is_err());
+//! ```
+
+use super::{blocks, state::bitmap_size, Bit, RawBuddies, Storage};
+use crate::{
+    sync::{Atomic, Backend, Ordering},
+    GenericBuddies,
+};
+use alloc_wg::{
+    alloc::{AllocRef, Global},
+    vec::Vec,
+};
+use core::{fmt, marker::PhantomData};
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeSeq, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+const FIELDS: &[&str] = &[
+    "max_order",
+    "base_shift",
+    "max_idx",
+    "base",
+    "allocations",
+    "available",
+    "allocated",
+];
+
+struct Bits<'a, S, B: Backend> {
+    raw: &'a RawBuddies<S, B>,
+    bit: Bit,
+}
+
+impl<T: Storage<B::Usize>, B: Backend> Serialize for Bits<'_, T, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let size = bitmap_size(self.raw.max_order);
+        let mut seq = serializer.serialize_seq(Some(size))?;
+        for i in 0..size {
+            seq.serialize_element(&self.raw.bitmap_byte(self.bit, i))?;
+        }
+        seq.end()
+    }
+}
+
+impl<A: AllocRef, B: Backend> Serialize for GenericBuddies<A, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let raw = &self.raw;
+        let mut state = serializer.serialize_struct("Buddies", FIELDS.len())?;
+        state.serialize_field("max_order", &raw.max_order)?;
+        state.serialize_field("base_shift", &raw.base_shift)?;
+        state.serialize_field("max_idx", &raw.max_idx)?;
+        state.serialize_field("base", &raw.base)?;
+        state.serialize_field("allocations", &raw.allocations.load(Ordering::Relaxed))?;
+        state.serialize_field(
+            "available",
+            &Bits {
+                raw,
+                bit: Bit::Available,
+            },
+        )?;
+        state.serialize_field(
+            "allocated",
+            &Bits {
+                raw,
+                bit: Bit::Allocated,
+            },
+        )?;
+        state.end()
+    }
+}
+
+/// reads a bit vector directly into the blocks
+struct BitsSeed<'a, B: Backend> {
+    raw: &'a RawBuddies<Vec<B::Usize, Global>, B>,
+    bit: Bit,
+}
+
+impl<'de, B: Backend> DeserializeSeed<'de> for BitsSeed<'_, B> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, B: Backend> Visitor<'de> for BitsSeed<'_, B> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a sequence of {} bytes", bitmap_size(self.raw.max_order))
+    }
+
+    fn visit_seq<V: SeqAccess<'de>>(self, mut seq: V) -> Result<(), V::Error> {
+        let size = bitmap_size(self.raw.max_order);
+        let num_blocks = blocks(self.raw.max_order);
+        for i in 0..size {
+            let byte: u8 = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+            let len = (num_blocks - i * 8).min(8);
+            if len < 8 && byte >> len != 0 {
+                return Err(de::Error::custom("bits past the last block are set"));
+            }
+            self.raw
+                .set_bitmap_byte(self.bit, i, byte)
+                .map_err(de::Error::custom)?;
+        }
+        if seq.next_element::<IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(size + 1, &self));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    MaxOrder,
+    BaseShift,
+    MaxIdx,
+    Base,
+    Allocations,
+    Available,
+    Allocated,
+    Ignore,
+}
+
+struct FieldVisitor;
+
+impl<'de> Visitor<'de> for FieldVisitor {
+    type Value = Field;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("field identifier")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Field, E> {
+        Ok(match value {
+            0 => Field::MaxOrder,
+            1 => Field::BaseShift,
+            2 => Field::MaxIdx,
+            3 => Field::Base,
+            4 => Field::Allocations,
+            5 => Field::Available,
+            6 => Field::Allocated,
+            _ => Field::Ignore,
+        })
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Field, E> {
+        Ok(match value {
+            "max_order" => Field::MaxOrder,
+            "base_shift" => Field::BaseShift,
+            "max_idx" => Field::MaxIdx,
+            "base" => Field::Base,
+            "allocations" => Field::Allocations,
+            "available" => Field::Available,
+            "allocated" => Field::Allocated,
+            _ => Field::Ignore,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_identifier(FieldVisitor)
+    }
+}
+
+fn finish<B: Backend, E: de::Error>(
+    raw: RawBuddies<Vec<B::Usize, Global>, B>,
+) -> Result<GenericBuddies<Global, B>, E> {
+    raw.recount();
+    raw.check_integrity().map_err(E::custom)?;
+    Ok(GenericBuddies::from_raw(raw))
+}
+
+struct BuddiesVisitor<B>(PhantomData<B>);
+
+impl<'de, B: Backend> Visitor<'de> for BuddiesVisitor<B> {
+    type Value = GenericBuddies<Global, B>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("struct Buddies")
+    }
+
+    fn visit_seq<V: SeqAccess<'de>>(self, mut seq: V) -> Result<Self::Value, V::Error> {
+        let max_order = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let base_shift = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let max_idx = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let base = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        let allocations = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(4, &self))?;
+
+        let raw =
+            RawBuddies::new_cleared_in(max_order, base_shift, max_idx, base, allocations, Global)
+                .map_err(de::Error::custom)?;
+        seq.next_element_seed(BitsSeed {
+            raw: &raw,
+            bit: Bit::Available,
+        })?
+        .ok_or_else(|| de::Error::invalid_length(5, &self))?;
+        seq.next_element_seed(BitsSeed {
+            raw: &raw,
+            bit: Bit::Allocated,
+        })?
+        .ok_or_else(|| de::Error::invalid_length(6, &self))?;
+
+        finish(raw)
+    }
+
+    fn visit_map<V: MapAccess<'de>>(self, mut map: V) -> Result<Self::Value, V::Error> {
+        let mut max_order = None;
+        let mut base_shift = None;
+        let mut max_idx = None;
+        let mut base = None;
+        let mut allocations = None;
+        let mut raw = None;
+        let mut available = false;
+        let mut allocated = false;
+
+        while let Some(key) = map.next_key()? {
+            match key {
+                Field::MaxOrder => set_once(&mut max_order, &mut map, "max_order")?,
+                Field::BaseShift => set_once(&mut base_shift, &mut map, "base_shift")?,
+                Field::MaxIdx => set_once(&mut max_idx, &mut map, "max_idx")?,
+                Field::Base => set_once(&mut base, &mut map, "base")?,
+                Field::Allocations => set_once(&mut allocations, &mut map, "allocations")?,
+                Field::Available | Field::Allocated => {
+                    let (bit, seen, name) = match key {
+                        Field::Available => (Bit::Available, &mut available, "available"),
+                        _ => (Bit::Allocated, &mut allocated, "allocated"),
+                    };
+                    if *seen {
+                        return Err(de::Error::duplicate_field(name));
+                    }
+                    *seen = true;
+
+                    // the bits are written directly into the blocks, so the parameters have
+                    // to be known at this point
+                    if raw.is_none() {
+                        raw = Some(
+                            RawBuddies::new_cleared_in(
+                                max_order.ok_or_else(|| de::Error::missing_field("max_order"))?,
+                                base_shift.ok_or_else(|| de::Error::missing_field("base_shift"))?,
+                                max_idx.ok_or_else(|| de::Error::missing_field("max_idx"))?,
+                                base.ok_or_else(|| de::Error::missing_field("base"))?,
+                                allocations
+                                    .ok_or_else(|| de::Error::missing_field("allocations"))?,
+                                Global,
+                            )
+                            .map_err(de::Error::custom)?,
+                        );
+                    }
+                    map.next_value_seed(BitsSeed {
+                        raw: raw.as_ref().unwrap(),
+                        bit,
+                    })?;
+                }
+                Field::Ignore => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        if !available {
+            return Err(de::Error::missing_field("available"));
+        }
+        if !allocated {
+            return Err(de::Error::missing_field("allocated"));
+        }
+        finish(raw.unwrap())
+    }
+}
+
+fn set_once<'de, T: Deserialize<'de>, V: MapAccess<'de>>(
+    value: &mut Option<T>,
+    map: &mut V,
+    name: &'static str,
+) -> Result<(), V::Error> {
+    if value.is_some() {
+        return Err(de::Error::duplicate_field(name));
+    }
+    *value = Some(map.next_value()?);
+    Ok(())
+}
+
+impl<'de, B: Backend> Deserialize<'de> for GenericBuddies<Global, B> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Buddies", FIELDS, BuddiesVisitor(PhantomData))
+    }
+}
@@ -0,0 +1,188 @@
+//! binary state export
+//!
+//! the layout is (all integers little endian):
+//!
+//! | offset | size | content                                     |
+//! |--------|------|---------------------------------------------|
+//! | 0      | 1    | version (1)                                 |
+//! | 1      | 1    | max_order                                   |
+//! | 2      | 1    | base_shift                                  |
+//! | 3      | 8    | max_idx                                     |
+//! | 11     | 8    | base                                        |
+//! | 19     | 8    | allocations counter                         |
+//! | 27     | n    | free bits, 8 blocks per byte                |
+//! | 27 + n | n    | allocated bits, 8 blocks per byte           |
+//!
+//! where `n` is `ceil((2^max_order - 1) / 8)`. the blocks are ordered by order and then by
+//! index, bit `i` of a byte belongs to block `8 * byte + i`.
+
+use super::{blocks, Bit, IntegrityError, RawBuddies, Storage, TryNewError};
+use crate::sync::{Atomic, Backend, Ordering};
+use alloc_wg::{alloc::AllocRef, vec::Vec};
+use core::{convert::TryInto, fmt};
+
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 27;
+
+/// error returned by [Buddies::export_state](crate::Buddies::export_state)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportError {
+    /// the buffer is too small, `required` bytes are needed
+    BufferTooSmall { required: usize },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExportError::BufferTooSmall { required } => {
+                write!(f, "buffer is too small ({} bytes required)", required)
+            }
+        }
+    }
+}
+
+/// error returned by [Buddies::import_state](crate::Buddies::import_state)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportError {
+    /// the input ends before the state is complete
+    Truncated,
+    /// the input is longer than the state
+    TrailingBytes,
+    /// the state was exported by an incompatible version
+    UnsupportedVersion(u8),
+    /// the header contains invalid parameters
+    InvalidParameters(&'static str),
+    /// creating the buddies failed, never [TryNewError::InvalidParameters]
+    New(TryNewError),
+    /// the blocks violate an invariant of the buddy tree
+    InvalidState(IntegrityError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ImportError::Truncated => write!(f, "state is truncated"),
+            ImportError::TrailingBytes => write!(f, "state has trailing bytes"),
+            ImportError::UnsupportedVersion(version) => {
+                write!(f, "unsupported state version {}", version)
+            }
+            ImportError::InvalidParameters(msg) => write!(f, "invalid parameters: {}", msg),
+            ImportError::New(err) => write!(f, "{}", err),
+            ImportError::InvalidState(err) => write!(f, "invalid state: {}", err),
+        }
+    }
+}
+
+impl From<TryNewError> for ImportError {
+    fn from(err: TryNewError) -> Self {
+        match err {
+            TryNewError::InvalidParameters(msg) => ImportError::InvalidParameters(msg),
+            err => ImportError::New(err),
+        }
+    }
+}
+
+/// the number of bytes of one bitmap
+pub(super) fn bitmap_size(max_order: usize) -> usize {
+    blocks(max_order).div_ceil(8)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+impl<B: Backend, S: Storage<B::Usize>> RawBuddies<S, B> {
+    pub fn state_size(&self) -> usize {
+        HEADER_SIZE + 2 * bitmap_size(self.max_order)
+    }
+
+    pub fn export_state(&self, out: &mut [u8]) -> Result<usize, ExportError> {
+        let required = self.state_size();
+        if out.len() < required {
+            return Err(ExportError::BufferTooSmall { required });
+        }
+
+        out[0] = VERSION;
+        out[1] = self.max_order as u8;
+        out[2] = self.base_shift as u8;
+        out[3..11].copy_from_slice(&(self.max_idx as u64).to_le_bytes());
+        out[11..19].copy_from_slice(&(self.base as u64).to_le_bytes());
+        let allocations = self.allocations.load(Ordering::Relaxed) as i64;
+        out[19..27].copy_from_slice(&allocations.to_le_bytes());
+
+        let bitmap_size = bitmap_size(self.max_order);
+        let (available, allocated) = out[HEADER_SIZE..required].split_at_mut(bitmap_size);
+        for i in 0..bitmap_size {
+            available[i] = self.bitmap_byte(Bit::Available, i);
+            allocated[i] = self.bitmap_byte(Bit::Allocated, i);
+        }
+
+        Ok(required)
+    }
+}
+
+impl<A: AllocRef, B: Backend> RawBuddies<Vec<B::Usize, A>, B> {
+    pub fn import_state_in(bytes: &[u8], a: A) -> Result<Self, ImportError> {
+        if bytes.is_empty() {
+            return Err(ImportError::Truncated);
+        }
+        if bytes[0] != VERSION {
+            return Err(ImportError::UnsupportedVersion(bytes[0]));
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(ImportError::Truncated);
+        }
+
+        let max_order = bytes[1] as usize;
+        let base_shift = bytes[2] as usize;
+        let max_idx = read_u64(bytes, 3)
+            .try_into()
+            .map_err(|_| ImportError::InvalidParameters("max_idx doesn't fit into usize"))?;
+        let base = read_u64(bytes, 11)
+            .try_into()
+            .map_err(|_| ImportError::InvalidParameters("base doesn't fit into usize"))?;
+        let allocations = (read_u64(bytes, 19) as i64)
+            .try_into()
+            .map_err(|_| ImportError::InvalidParameters("allocations don't fit into isize"))?;
+
+        // check the length before allocating the blocks
+        if max_order == 0 || max_order >= super::WORD_BITS {
+            return Err(ImportError::InvalidParameters("invalid max_order"));
+        }
+        let bitmap_size = bitmap_size(max_order);
+        let required = HEADER_SIZE + 2 * bitmap_size;
+        if bytes.len() < required {
+            return Err(ImportError::Truncated);
+        }
+        if bytes.len() > required {
+            return Err(ImportError::TrailingBytes);
+        }
+
+        let buddies = Self::new_cleared_in(max_order, base_shift, max_idx, base, allocations, a)?;
+
+        let (available, allocated) = bytes[HEADER_SIZE..].split_at(bitmap_size);
+        // there are always unused bits because the number of blocks is odd
+        let unused_bits = bitmap_size * 8 - blocks(max_order);
+        if available[bitmap_size - 1] >> (8 - unused_bits) != 0
+            || allocated[bitmap_size - 1] >> (8 - unused_bits) != 0
+        {
+            return Err(ImportError::InvalidParameters(
+                "bits past the last block are set",
+            ));
+        }
+        for i in 0..bitmap_size {
+            buddies
+                .set_bitmap_byte(Bit::Available, i, available[i])
+                .map_err(ImportError::InvalidState)?;
+            buddies
+                .set_bitmap_byte(Bit::Allocated, i, allocated[i])
+                .map_err(ImportError::InvalidState)?;
+        }
+
+        buddies.recount();
+        buddies
+            .check_integrity()
+            .map_err(ImportError::InvalidState)?;
+        Ok(buddies)
+    }
+}
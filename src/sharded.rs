@@ -0,0 +1,220 @@
+use crate::{raw::RawBuddies, sync::AtomicUsize};
+use alloc_wg::{
+    alloc::{AllocRef, Global},
+    vec::Vec,
+};
+
+/// a collection of independent buddy trees with the same capacity laid out back to back
+///
+/// threads allocating from different shards don't touch the same atomics, so they don't slow
+/// each other down. every allocation prefers the shard chosen by a hint (eg the id of the cpu)
+/// and only falls back to the other shards when it doesn't fit.
+///
+/// allocations never span shards, so allocations bigger than a shard always fail. use a separate
+/// [Buddies](crate::Buddies) for them.
+pub struct ShardedBuddies<A: AllocRef = Global> {
+    shards: Vec<RawBuddies<Vec<AtomicUsize, A>>, A>,
+    shard_capacity: usize,
+}
+
+impl ShardedBuddies<Global> {
+    /// create `num_shards` shards with `shard_capacity` each
+    ///
+    /// `shard_capacity` must not be zero and be divisable by `multiplier`
+    ///
+    /// # Panics
+    /// panics if:
+    /// - `num_shards` is zero
+    /// - `shard_capacity` is zero or not divisable by `multiplier`
+    /// - `multiplier` is not a power of two
+    /// - the total capacity overflows
+    /// ```
+    /// use buddy_allocator::ShardedBuddies;
+    ///
+    /// let sharded = ShardedBuddies::new(4, 16, 1);
+    /// assert_eq!(sharded.capacity(), 64);
+    /// assert_eq!(sharded.num_shards(), 4);
+    /// assert_eq!(sharded.allocate(4, 1, 2), Some(32));
+    /// assert_eq!(sharded.allocate(4, 1, 6), Some(36));
+    /// assert_eq!(sharded.allocate(32, 1, 0), None);
+    /// ```
+    pub fn new(num_shards: usize, shard_capacity: usize, multiplier: usize) -> Self {
+        ShardedBuddies::new_in(num_shards, shard_capacity, multiplier, Global)
+    }
+}
+
+impl<A: AllocRef> ShardedBuddies<A> {
+    /// see [ShardedBuddies::new](ShardedBuddies::new)
+    pub fn new_in(num_shards: usize, shard_capacity: usize, multiplier: usize, a: A) -> Self
+    where
+        A: Clone,
+    {
+        assert_ne!(num_shards, 0, "there must be at least one shard");
+        assert!(
+            shard_capacity.checked_mul(num_shards).is_some(),
+            "capacity overflows"
+        );
+
+        let mut shards = Vec::with_capacity_in(num_shards, a.clone());
+        for i in 0..num_shards {
+            shards.push(RawBuddies::with_capacity_at(
+                i * shard_capacity,
+                shard_capacity,
+                multiplier,
+                a.clone(),
+            ));
+        }
+
+        ShardedBuddies {
+            shards,
+            shard_capacity,
+        }
+    }
+
+    /// return the capacity of all shards together
+    pub fn capacity(&self) -> usize {
+        self.shards.len() * self.shard_capacity
+    }
+
+    /// return the capacity of a single shard
+    pub fn shard_capacity(&self) -> usize {
+        self.shard_capacity
+    }
+
+    /// return the number of shards
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// return the number of outstanding allocations in all shards
+    ///
+    /// see [Buddies::num_allocations](crate::Buddies::num_allocations)
+    pub fn num_allocations(&self) -> usize {
+        self.shards.iter().map(RawBuddies::num_allocations).sum()
+    }
+
+    /// return the sum of the sizes of the free buddies in all shards
+    ///
+    /// see [Buddies::free_bytes](crate::Buddies::free_bytes)
+    /// ```
+    /// use buddy_allocator::ShardedBuddies;
+    ///
+    /// let sharded = ShardedBuddies::new(3, 16, 1);
+    /// sharded.allocate(8, 1, 0).unwrap();
+    /// sharded.allocate(2, 1, 1).unwrap();
+    /// assert_eq!(sharded.free_bytes(), 38);
+    /// ```
+    pub fn free_bytes(&self) -> usize {
+        self.shards.iter().map(RawBuddies::free_bytes).sum()
+    }
+
+    /// check if there are any allocations in any shard
+    ///
+    /// if this returns true all shards are rendered useless like with
+    /// [Buddies::is_unused](crate::Buddies::is_unused). if it returns false all shards stay
+    /// usable, but concurrent allocations from the shards checked so far may fail while it runs.
+    /// ```
+    /// use buddy_allocator::ShardedBuddies;
+    ///
+    /// let sharded = ShardedBuddies::new(2, 16, 1);
+    /// let idx = sharded.allocate(1, 1, 1).unwrap();
+    /// assert!(!sharded.is_unused());
+    /// assert_eq!(sharded.allocate(1, 1, 0), Some(0));
+    /// sharded.deallocate(0, 1);
+    /// sharded.deallocate(idx, 1);
+    /// # #[cfg(feature = "quarantine")] sharded.flush_quarantine();
+    /// assert!(sharded.is_unused());
+    /// assert_eq!(sharded.allocate(1, 1, 0), None);
+    /// ```
+    pub fn is_unused(&self) -> bool {
+        for (i, shard) in self.shards.iter().enumerate() {
+            if !shard.is_unused() {
+                for shard in &self.shards[..i] {
+                    shard.unpoison();
+                }
+                return false;
+            }
+        }
+        true
+    }
+
+    /// check if there are any allocations in any shard without changing anything
+    ///
+    /// see [Buddies::is_currently_unused](crate::Buddies::is_currently_unused)
+    pub fn is_currently_unused(&self) -> bool {
+        self.num_allocations() == 0
+    }
+
+    /// release the buddies held back by the quarantines of all shards
+    ///
+    /// see [Buddies::flush_quarantine](crate::Buddies::flush_quarantine)
+    #[cfg(feature = "quarantine")]
+    pub fn flush_quarantine(&self) {
+        self.shards.iter().for_each(RawBuddies::flush_quarantine)
+    }
+
+    /// get the real size of an allocation for a given size
+    ///
+    /// see [Buddies::real_size_for_allocation](crate::Buddies::real_size_for_allocation)
+    pub fn real_size_for_allocation(&self, size: usize) -> usize {
+        self.shards[0].real_size_for_allocation(size)
+    }
+
+    /// get the real size of an allocation for a given size, `None` if no block is big enough
+    ///
+    /// see [Buddies::try_real_size_for_allocation](crate::Buddies::try_real_size_for_allocation)
+    pub fn try_real_size_for_allocation(&self, size: usize) -> Option<usize> {
+        self.shards[0].try_real_size_for_allocation(size)
+    }
+
+    fn shard(&self, idx: usize) -> &RawBuddies<Vec<AtomicUsize, A>> {
+        self.shards
+            .get(idx / self.shard_capacity)
+            .unwrap_or_else(|| panic!("idx {} is out of bounds", idx))
+    }
+
+    /// allocate a buddy with a given size
+    ///
+    /// the shard `hint % num_shards` is tried first, then the following ones in order. fails if
+    /// `size` or `align` is bigger than a shard.
+    ///
+    /// see [Buddies::allocate](crate::Buddies::allocate)
+    /// ```
+    /// use buddy_allocator::ShardedBuddies;
+    ///
+    /// let sharded = ShardedBuddies::new(3, 8, 1);
+    /// assert_eq!(sharded.allocate(8, 1, 1), Some(8));
+    /// // the preferred shard is full
+    /// assert_eq!(sharded.allocate(4, 1, 1), Some(16));
+    /// assert_eq!(sharded.allocate(8, 1, 2), Some(0));
+    /// assert_eq!(sharded.allocate(4, 1, 5), Some(20));
+    /// assert_eq!(sharded.allocate(1, 1, 1), None);
+    /// ```
+    pub fn allocate(&self, size: usize, align: usize, hint: usize) -> Option<usize> {
+        if size > self.shard_capacity || align > self.shard_capacity {
+            return None;
+        }
+        let start = hint % self.shards.len();
+        self.shards[start..]
+            .iter()
+            .chain(&self.shards[..start])
+            .find_map(|shard| shard.allocate_with_size(size, align))
+    }
+
+    /// deallocate a buddy with a given size
+    ///
+    /// see [Buddies::deallocate](crate::Buddies::deallocate)
+    pub fn deallocate(&self, idx: usize, size: usize) {
+        self.shard(idx).deallocate_with_size(idx, size);
+    }
+
+    /// get the size of the buddy allocated at a given index
+    ///
+    /// see [Buddies::allocation_size_at](crate::Buddies::allocation_size_at)
+    pub fn allocation_size_at(&self, idx: usize) -> Option<usize> {
+        if idx >= self.capacity() {
+            return None;
+        }
+        self.shard(idx).allocation_size_at(idx)
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    raw::{words, RawBuddies},
+    sync::AtomicUsize,
+    IntegrityError,
+};
+use alloc_wg::alloc::ReallocPlacement;
+
+/// a [Buddies](crate::Buddies) variant that stores its bits inline
+///
+/// `ORDER` is the max order. no allocator is needed, so this can be used to bootstrap the first
+/// allocator. [StaticBuddies::new](StaticBuddies::new) is a const fn which allows declaring
+/// instances in a `static`.
+///
+/// the size depends on `ORDER` through [words](crate::words), so this requires the
+/// `generic_const_exprs` feature in the crate using it.
+/// ```
+/// #![feature(generic_const_exprs)]
+/// #![allow(incomplete_features)]
+/// use buddy_allocator::StaticBuddies;
+///
+/// static FRAMES: StaticBuddies<11> = StaticBuddies::new(4096, Some(3 * 1024 * 1024));
+/// assert_eq!(FRAMES.capacity(), 3 * 1024 * 1024);
+/// let idx = FRAMES.allocate(8192, 4096).unwrap();
+/// FRAMES.deallocate(idx, 8192);
+/// ```
+pub struct StaticBuddies<const ORDER: usize>
+where
+    [(); words(ORDER)]:,
+{
+    raw: RawBuddies<[AtomicUsize; words(ORDER)]>,
+}
+
+impl<const ORDER: usize> StaticBuddies<ORDER>
+where
+    [(); words(ORDER)]:,
+{
+    /// create a new instance
+    ///
+    /// see [Buddies::new](crate::Buddies::new)
+    ///
+    /// # Panics
+    /// panics if:
+    /// - `ORDER` is zero
+    /// - `multiplier` is not a power of two
+    /// - `max_idx` is not a valid index
+    ///
+    /// in a const context this is a compile time error.
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// #![allow(incomplete_features)]
+    /// use buddy_allocator::StaticBuddies;
+    ///
+    /// let buddies = StaticBuddies::<3>::new(1, None);
+    /// assert_eq!(buddies.capacity(), 4);
+    /// let buddies = StaticBuddies::<3>::new(4, Some(12));
+    /// assert_eq!(buddies.capacity(), 12);
+    /// ```
+    pub const fn new(multiplier: usize, max_idx: Option<usize>) -> Self {
+        StaticBuddies {
+            raw: RawBuddies::new_array(ORDER, multiplier, max_idx),
+        }
+    }
+
+    /// return the capacity
+    ///
+    /// see [Buddies::capacity](crate::Buddies::capacity)
+    pub fn capacity(&self) -> usize {
+        self.raw.capacity()
+    }
+
+    /// return the number of outstanding allocations
+    ///
+    /// see [Buddies::num_allocations](crate::Buddies::num_allocations)
+    pub fn num_allocations(&self) -> usize {
+        self.raw.num_allocations()
+    }
+
+    /// check if there are any allocations
+    ///
+    /// see [Buddies::is_unused](crate::Buddies::is_unused)
+    /// # Safety
+    /// calling this method is equivalent to trying to allocate the entire memory inside at once thus rendering it useless after it returned true
+    pub fn is_unused(&self) -> bool {
+        self.raw.is_unused()
+    }
+
+    /// check if there are any allocations without changing anything
+    ///
+    /// see [Buddies::is_currently_unused](crate::Buddies::is_currently_unused)
+    pub fn is_currently_unused(&self) -> bool {
+        self.raw.num_allocations() == 0
+    }
+
+    /// check if [StaticBuddies::is_unused] returned true
+    ///
+    /// see [Buddies::is_poisoned](crate::Buddies::is_poisoned)
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.is_poisoned()
+    }
+
+    /// release all buddies held back by the quarantine
+    ///
+    /// see [Buddies::flush_quarantine](crate::Buddies::flush_quarantine)
+    #[cfg(feature = "quarantine")]
+    pub fn flush_quarantine(&self) {
+        self.raw.flush_quarantine()
+    }
+
+    /// check every invariant of the buddy tree
+    ///
+    /// see [Buddies::verify_integrity](crate::Buddies::verify_integrity)
+    pub fn verify_integrity(&self) -> Result<(), IntegrityError> {
+        self.raw.check_integrity()
+    }
+
+    /// get the real size of an allocation for a given size
+    ///
+    /// see [Buddies::real_size_for_allocation](crate::Buddies::real_size_for_allocation)
+    pub fn real_size_for_allocation(&self, size: usize) -> usize {
+        self.raw.real_size_for_allocation(size)
+    }
+
+    /// get the real size of an allocation for a given size, `None` if no block is big enough
+    ///
+    /// see [Buddies::try_real_size_for_allocation](crate::Buddies::try_real_size_for_allocation)
+    pub fn try_real_size_for_allocation(&self, size: usize) -> Option<usize> {
+        self.raw.try_real_size_for_allocation(size)
+    }
+
+    /// try to allocate a buddy with a given size at a given index
+    ///
+    /// see [Buddies::allocate_at](crate::Buddies::allocate_at)
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// #![allow(incomplete_features)]
+    /// use buddy_allocator::StaticBuddies;
+    ///
+    /// let buddies = StaticBuddies::<5>::new(1, None);
+    /// assert_eq!(buddies.allocate_at(1, 0), true);
+    /// assert_eq!(buddies.allocate_at(2, 2), true);
+    /// assert_eq!(buddies.allocate_at(1, 3), false);
+    /// assert_eq!(buddies.allocate_at(2, 8), true);
+    /// ```
+    pub fn allocate_at(&self, size: usize, idx: usize) -> bool {
+        self.raw.allocate_at_with_size(size, idx)
+    }
+
+    /// allocate a buddy with a given size
+    ///
+    /// see [Buddies::allocate](crate::Buddies::allocate)
+    /// ```
+    /// #![feature(generic_const_exprs)]
+    /// #![allow(incomplete_features)]
+    /// use buddy_allocator::StaticBuddies;
+    ///
+    /// let buddies = StaticBuddies::<5>::new(1, None);
+    /// assert_eq!(buddies.allocate(1, 1).unwrap(), 0);
+    /// assert_eq!(buddies.allocate(2, 1).unwrap(), 2);
+    /// assert_eq!(buddies.allocate(2, 1).unwrap(), 4);
+    /// assert_eq!(buddies.allocate(2, 4).unwrap(), 8);
+    /// ```
+    pub fn allocate(&self, size: usize, align: usize) -> Option<usize> {
+        self.raw.allocate_with_size(size, align)
+    }
+
+    /// deallocate a buddy with a given size
+    ///
+    /// see [Buddies::deallocate](crate::Buddies::deallocate)
+    pub fn deallocate(&self, idx: usize, size: usize) {
+        self.raw.deallocate_with_size(idx, size);
+    }
+
+    /// get the size of the buddy allocated at a given index
+    ///
+    /// see [Buddies::allocation_size_at](crate::Buddies::allocation_size_at)
+    pub fn allocation_size_at(&self, idx: usize) -> Option<usize> {
+        self.raw.allocation_size_at(idx)
+    }
+
+    /// deallocate the buddy allocated at a given index
+    ///
+    /// see [Buddies::deallocate_by_idx](crate::Buddies::deallocate_by_idx)
+    pub fn deallocate_by_idx(&self, idx: usize) {
+        let size = self
+            .allocation_size_at(idx)
+            .unwrap_or_else(|| panic!("{} is not allocated", idx));
+        self.deallocate(idx, size)
+    }
+
+    /// shrink a buddy
+    ///
+    /// see [Buddies::shrink](crate::Buddies::shrink)
+    pub fn shrink(&self, idx: usize, old_size: usize, new_size: usize) {
+        self.raw.shrink_with_size(idx, old_size, new_size)
+    }
+
+    /// grow a buddy
+    ///
+    /// see [Buddies::grow](crate::Buddies::grow)
+    /// ```
+    /// #![feature(allocator_api, generic_const_exprs)]
+    /// #![allow(incomplete_features)]
+    /// use alloc_wg::alloc::ReallocPlacement;
+    /// use buddy_allocator::StaticBuddies;
+    ///
+    /// let buddies = StaticBuddies::<3>::new(1, None);
+    /// let idx = buddies.allocate(0, 1).unwrap();
+    /// let idx = buddies.grow(idx, 0, 1, ReallocPlacement::InPlace).unwrap();
+    /// let idx = buddies.grow(idx, 1, 2, ReallocPlacement::MayMove).unwrap();
+    /// buddies.grow(idx, 2, 3, ReallocPlacement::InPlace).unwrap();
+    /// ```
+    pub fn grow(
+        &self,
+        idx: usize,
+        old_size: usize,
+        new_size: usize,
+        placement: ReallocPlacement,
+    ) -> Option<usize> {
+        self.raw
+            .grow_or_move_with_size(idx, old_size, new_size, placement)
+    }
+}
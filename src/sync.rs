@@ -0,0 +1,202 @@
+//! the atomics used by the buddies
+//!
+//! with `--cfg loom` they are replaced by the ones from loom so the concurrency tests can check
+//! all interleavings. with the `portable-atomic` feature they come from portable-atomic, which
+//! can emulate them on targets without compare and swap.
+
+#[cfg(all(not(loom), not(feature = "portable-atomic")))]
+pub use core::{
+    hint::spin_loop,
+    sync::atomic::{fence, AtomicBool, AtomicIsize, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
+};
+#[cfg(loom)]
+pub use loom::{
+    hint::spin_loop,
+    sync::atomic::{fence, AtomicBool, AtomicIsize, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
+};
+#[cfg(all(not(loom), feature = "portable-atomic"))]
+pub use portable_atomic::{
+    fence, hint::spin_loop, AtomicBool, AtomicIsize, AtomicPtr, AtomicU32, AtomicUsize, Ordering,
+};
+
+use core::ops::Deref;
+
+/// the counters and hints kept per order
+///
+/// with the `compact` feature they are 32 bits wide, which limits the number of blocks to
+/// `u32::MAX`
+#[cfg(not(feature = "compact"))]
+pub type Index = usize;
+#[cfg(feature = "compact")]
+pub type Index = u32;
+#[cfg(not(feature = "compact"))]
+pub type AtomicIndex = AtomicUsize;
+#[cfg(feature = "compact")]
+pub type AtomicIndex = AtomicU32;
+
+/// convert a value loaded from an [AtomicIndex]
+#[allow(clippy::unnecessary_cast)]
+pub fn widen(index: Index) -> usize {
+    index as usize
+}
+
+/// the operations the buddies use on their state
+///
+/// the orderings are the ones of the atomics, implementations without concurrency may ignore
+/// them.
+pub trait Atomic<T> {
+    fn new(value: T) -> Self;
+    fn load(&self, order: Ordering) -> T;
+    fn store(&self, value: T, order: Ordering);
+    fn swap(&self, value: T, order: Ordering) -> T;
+    fn compare_exchange(
+        &self,
+        current: T,
+        new: T,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<T, T>;
+    fn compare_exchange_weak(
+        &self,
+        current: T,
+        new: T,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<T, T>;
+}
+
+/// the operations the buddies use on their counters and words
+pub trait AtomicInt<T>: Atomic<T> {
+    fn fetch_add(&self, value: T, order: Ordering) -> T;
+    fn fetch_sub(&self, value: T, order: Ordering) -> T;
+    fn fetch_or(&self, value: T, order: Ordering) -> T;
+    fn fetch_and(&self, value: T, order: Ordering) -> T;
+    fn fetch_min(&self, value: T, order: Ordering) -> T;
+}
+
+/// the types the buddies store their state in
+///
+/// [Atomics] is used by [Buddies](crate::Buddies), the cells of
+/// [unsync::Cells](crate::unsync::Cells) are only usable from one thread but don't need atomic
+/// instructions.
+///
+/// # Safety
+/// zeroed memory must be a valid value of zero for [Backend::Usize], the bits of the buddies
+/// are allocated that way.
+pub unsafe trait Backend {
+    type Usize: AtomicInt<usize>;
+    type Isize: AtomicInt<isize>;
+    type Index: AtomicInt<Index>;
+    type Bool: Atomic<bool>;
+    type Ptr: Atomic<*mut ()>;
+
+    /// a fence ordering the operations on all of the types above
+    fn fence(order: Ordering);
+}
+
+/// the backend using atomics, see [Backend]
+pub struct Atomics;
+
+// an atomic has the same in-memory representation as its integer
+unsafe impl Backend for Atomics {
+    type Usize = AtomicUsize;
+    type Isize = AtomicIsize;
+    type Index = AtomicIndex;
+    type Bool = AtomicBool;
+    type Ptr = AtomicPtr<()>;
+
+    fn fence(order: Ordering) {
+        fence(order)
+    }
+}
+
+macro_rules! impl_atomic {
+    ($atomic:ty, $value:ty) => {
+        impl Atomic<$value> for $atomic {
+            fn new(value: $value) -> Self {
+                <$atomic>::new(value)
+            }
+
+            fn load(&self, order: Ordering) -> $value {
+                <$atomic>::load(self, order)
+            }
+
+            fn store(&self, value: $value, order: Ordering) {
+                <$atomic>::store(self, value, order)
+            }
+
+            fn swap(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::swap(self, value, order)
+            }
+
+            fn compare_exchange(
+                &self,
+                current: $value,
+                new: $value,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<$value, $value> {
+                <$atomic>::compare_exchange(self, current, new, success, failure)
+            }
+
+            fn compare_exchange_weak(
+                &self,
+                current: $value,
+                new: $value,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<$value, $value> {
+                <$atomic>::compare_exchange_weak(self, current, new, success, failure)
+            }
+        }
+    };
+}
+
+macro_rules! impl_atomic_int {
+    ($atomic:ty, $value:ty) => {
+        impl_atomic!($atomic, $value);
+
+        impl AtomicInt<$value> for $atomic {
+            fn fetch_add(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::fetch_add(self, value, order)
+            }
+
+            fn fetch_sub(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::fetch_sub(self, value, order)
+            }
+
+            fn fetch_or(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::fetch_or(self, value, order)
+            }
+
+            fn fetch_and(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::fetch_and(self, value, order)
+            }
+
+            fn fetch_min(&self, value: $value, order: Ordering) -> $value {
+                <$atomic>::fetch_min(self, value, order)
+            }
+        }
+    };
+}
+
+impl_atomic_int!(AtomicUsize, usize);
+impl_atomic_int!(AtomicIsize, isize);
+impl_atomic_int!(AtomicU32, u32);
+impl_atomic!(AtomicBool, bool);
+impl_atomic!(AtomicPtr<()>, *mut ());
+
+/// a value on its own cache line
+///
+/// counters updated by every operation are wrapped in this so updating them doesn't invalidate
+/// the cache lines of other fields or of whatever is stored next to the buddies.
+#[repr(align(64))]
+pub struct CachePadded<T>(pub T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
@@ -0,0 +1,115 @@
+//! buddies for a single thread
+//!
+//! [Buddies] has the same API as [crate::Buddies], but it keeps its state in [Cell]s instead of
+//! atomics. it can't be shared between threads, in return it doesn't need any atomic
+//! instructions and doesn't pay for them.
+
+use crate::{
+    sync::{Atomic, AtomicInt, Backend, Index, Ordering},
+    GenericBuddies,
+};
+use alloc_wg::alloc::Global;
+use core::cell::Cell;
+
+/// buddies for a single thread, see [crate::Buddies]
+/// ```
+/// use buddy_allocator::unsync::Buddies;
+///
+/// let buddies = Buddies::new(5, 1, None);
+/// let idx1 = buddies.allocate(1, 1).unwrap();
+/// let idx2 = buddies.allocate(2, 1).unwrap();
+/// assert_eq!((idx1, idx2), (0, 2));
+/// buddies.deallocate(idx1, 1);
+/// buddies.deallocate(idx2, 2);
+/// # #[cfg(feature = "quarantine")] buddies.flush_quarantine();
+/// assert!(buddies.is_unused());
+/// ```
+///
+/// it can't be shared between threads
+/// ```compile_fail
+/// fn shared<T: Sync>(_: &T) {}
+/// shared(&buddy_allocator::unsync::Buddies::new(5, 1, None));
+/// ```
+pub type Buddies<A = Global> = GenericBuddies<A, Cells>;
+
+/// the backend of [Buddies], see [Backend]
+pub struct Cells;
+
+// a cell has the same in-memory representation as its value
+unsafe impl Backend for Cells {
+    type Usize = Cell<usize>;
+    type Isize = Cell<isize>;
+    type Index = Cell<Index>;
+    type Bool = Cell<bool>;
+    type Ptr = Cell<*mut ()>;
+
+    fn fence(_: Ordering) {}
+}
+
+impl<T: Copy + PartialEq> Atomic<T> for Cell<T> {
+    fn new(value: T) -> Self {
+        Cell::new(value)
+    }
+
+    fn load(&self, _: Ordering) -> T {
+        self.get()
+    }
+
+    fn store(&self, value: T, _: Ordering) {
+        self.set(value)
+    }
+
+    fn swap(&self, value: T, _: Ordering) -> T {
+        self.replace(value)
+    }
+
+    fn compare_exchange(&self, current: T, new: T, _: Ordering, _: Ordering) -> Result<T, T> {
+        let value = self.get();
+        if value == current {
+            self.set(new);
+            Ok(value)
+        } else {
+            Err(value)
+        }
+    }
+
+    fn compare_exchange_weak(
+        &self,
+        current: T,
+        new: T,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<T, T> {
+        self.compare_exchange(current, new, success, failure)
+    }
+}
+
+macro_rules! impl_cell_int {
+    ($value:ty) => {
+        impl AtomicInt<$value> for Cell<$value> {
+            fn fetch_add(&self, value: $value, _: Ordering) -> $value {
+                self.replace(self.get().wrapping_add(value))
+            }
+
+            fn fetch_sub(&self, value: $value, _: Ordering) -> $value {
+                self.replace(self.get().wrapping_sub(value))
+            }
+
+            fn fetch_or(&self, value: $value, _: Ordering) -> $value {
+                self.replace(self.get() | value)
+            }
+
+            fn fetch_and(&self, value: $value, _: Ordering) -> $value {
+                self.replace(self.get() & value)
+            }
+
+            fn fetch_min(&self, value: $value, _: Ordering) -> $value {
+                self.replace(self.get().min(value))
+            }
+        }
+    };
+}
+
+impl_cell_int!(usize);
+impl_cell_int!(isize);
+impl_cell_int!(u32);
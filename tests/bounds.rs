@@ -0,0 +1,91 @@
+//! out of bounds indices have to panic in release builds too
+//!
+//! the internal accesses only check the bounds with debug assertions, so run with
+//! `cargo test --release --test bounds`
+#![cfg(not(loom))]
+
+use alloc_wg::alloc::ReallocPlacement;
+use buddy_allocator::Buddies;
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn allocate_at_behind_the_capacity() {
+    // the blocks behind 12 share a word with the ones in front of it
+    let buddies = Buddies::with_capacity(12, 1);
+    buddies.allocate_at(1, 16);
+}
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn allocate_at_behind_the_last_word() {
+    let buddies = Buddies::with_capacity(96, 1);
+    buddies.allocate_at(1, 1 << 20);
+}
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn allocate_at_overlapping_the_end() {
+    let buddies = Buddies::with_capacity(12, 1);
+    buddies.allocate_at(8, 8);
+}
+
+#[test]
+#[should_panic(expected = "not aligned")]
+fn allocate_at_unaligned() {
+    let buddies = Buddies::new(5, 1, None);
+    buddies.allocate_at(2, 1);
+}
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn deallocate_out_of_bounds() {
+    let buddies = Buddies::with_capacity(12, 1);
+    buddies.allocate(1, 1).unwrap();
+    buddies.deallocate(16, 1);
+}
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn deallocate_order_out_of_bounds() {
+    let buddies = Buddies::with_capacity(12, 1);
+    buddies.allocate(1, 1).unwrap();
+    buddies.deallocate_order(16, buddies.order_for_size(1));
+}
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn split_out_of_bounds() {
+    let buddies = Buddies::with_capacity(12, 1);
+    buddies.split(16, 2);
+}
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn shrink_out_of_bounds() {
+    let buddies = Buddies::with_capacity(12, 1);
+    buddies.shrink(16, 2, 1);
+}
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn shrink_with_placement_out_of_bounds() {
+    let buddies = Buddies::with_capacity(12, 1);
+    buddies.shrink_with_placement(16, 2, 1, 1, ReallocPlacement::MayMove);
+}
+
+#[test]
+#[should_panic(expected = "is out of bounds")]
+fn grow_out_of_bounds() {
+    let buddies = Buddies::with_capacity(12, 1);
+    buddies.grow(16, 1, 2, ReallocPlacement::MayMove);
+}
+
+#[test]
+fn allocation_size_at_out_of_bounds() {
+    let buddies = Buddies::with_capacity(12, 1);
+    assert!(buddies.allocate_at(4, 8));
+    assert_eq!(buddies.allocation_size_at(8), Some(4));
+    assert_eq!(buddies.allocation_size_at(12), None);
+    assert_eq!(buddies.allocation_size_at(16), None);
+    assert_eq!(buddies.allocation_size_at(usize::MAX), None);
+}
@@ -0,0 +1,91 @@
+//! loom models for the critical interleavings of the buddies
+//!
+//! run with `RUSTFLAGS="--cfg loom" cargo test --test loom --release --no-default-features`
+#![cfg(loom)]
+
+use alloc_wg::alloc::ReallocPlacement;
+use buddy_allocator::Buddies;
+use loom::{sync::Arc, thread};
+
+/// checks that every block was returned and merged back into the root
+fn assert_all_free(buddies: &Buddies) {
+    #[cfg(feature = "quarantine")]
+    buddies.flush_quarantine();
+    assert_eq!(buddies.num_allocations(), 0);
+    assert_eq!(buddies.allocate(2, 1), Some(0));
+}
+
+#[test]
+fn allocate_same_order() {
+    loom::model(|| {
+        let buddies = Arc::new(Buddies::new(2, 1, None));
+
+        let b = buddies.clone();
+        let t = thread::spawn(move || b.allocate(1, 1));
+        let first = buddies.allocate(1, 1);
+        let second = t.join().unwrap();
+
+        // the allocation that doesn't split the root waits for the other one to free its buddy
+        assert!(first.is_some() && second.is_some());
+        assert_ne!(first, second);
+
+        for &idx in [first, second].iter().flatten() {
+            buddies.deallocate(idx, 1);
+        }
+        assert_all_free(&buddies);
+    });
+}
+
+#[test]
+fn allocate_racing_deallocate_of_buddy() {
+    loom::model(|| {
+        let buddies = Arc::new(Buddies::new(2, 1, None));
+        let first = buddies.allocate(1, 1).unwrap();
+
+        let b = buddies.clone();
+        let t = thread::spawn(move || b.deallocate(first, 1));
+        let second = buddies.allocate(1, 1).unwrap();
+        t.join().unwrap();
+
+        buddies.deallocate(second, 1);
+        assert_all_free(&buddies);
+    });
+}
+
+#[test]
+fn grow_racing_deallocate_of_buddy() {
+    loom::model(|| {
+        let buddies = Arc::new(Buddies::new(2, 1, None));
+        let first = buddies.allocate(1, 1).unwrap();
+        let second = buddies.allocate(1, 1).unwrap();
+
+        let b = buddies.clone();
+        let t = thread::spawn(move || b.deallocate(second, 1));
+        let grown = buddies.grow(first, 1, 2, ReallocPlacement::InPlace);
+        t.join().unwrap();
+
+        match grown {
+            Some(idx) => {
+                assert_eq!(idx, first);
+                buddies.deallocate(idx, 2);
+            }
+            None => buddies.deallocate(first, 1),
+        }
+        assert_all_free(&buddies);
+    });
+}
+
+#[test]
+fn is_unused_racing_allocate() {
+    loom::model(|| {
+        let buddies = Arc::new(Buddies::new(2, 1, None));
+
+        let b = buddies.clone();
+        let t = thread::spawn(move || b.is_unused());
+        let allocated = buddies.allocate(1, 1);
+        let unused = t.join().unwrap();
+
+        // once the buddies were reported as unused no allocation may succeed
+        assert!(!(unused && allocated.is_some()));
+    });
+}
@@ -0,0 +1,71 @@
+//! the buddies with the atomics of portable-atomic
+//!
+//! on the host portable-atomic uses the native instructions, but every atomic goes through its
+//! types just like on a target without compare and swap.
+//!
+//! run with `cargo test --test portable_atomic --features portable-atomic`
+#![cfg(all(feature = "portable-atomic", not(loom)))]
+
+use alloc_wg::alloc::ReallocPlacement;
+use buddy_allocator::Buddies;
+use std::{sync::Arc, thread};
+
+#[test]
+fn allocate_and_deallocate() {
+    let buddies = Buddies::new(5, 1, None);
+    let idx1 = buddies.allocate(1, 1).unwrap();
+    let idx2 = buddies.allocate(2, 1).unwrap();
+    let idx3 = buddies.allocate(4, 4).unwrap();
+    assert_eq!((idx1, idx2, idx3), (0, 2, 4));
+    assert_eq!(buddies.num_allocations(), 3);
+
+    buddies.deallocate(idx1, 1);
+    buddies.deallocate(idx2, 2);
+    buddies.deallocate(idx3, 4);
+    #[cfg(feature = "quarantine")]
+    buddies.flush_quarantine();
+    assert!(buddies.is_unused());
+    assert!(buddies.verify_integrity().is_ok());
+}
+
+#[test]
+fn grow_and_shrink() {
+    let buddies = Buddies::new(5, 1, None);
+    let idx = buddies.allocate(2, 1).unwrap();
+    assert_eq!(
+        buddies.grow(idx, 2, 4, ReallocPlacement::InPlace),
+        Some(idx)
+    );
+    buddies.shrink(idx, 4, 1);
+    assert!(buddies.verify_integrity().is_ok());
+
+    buddies.deallocate(idx, 1);
+    #[cfg(feature = "quarantine")]
+    buddies.flush_quarantine();
+    assert!(buddies.is_unused());
+}
+
+#[test]
+fn concurrent() {
+    let buddies = Arc::new(Buddies::new(12, 1, None));
+    let threads = (0..4)
+        .map(|i| {
+            let buddies = buddies.clone();
+            thread::spawn(move || {
+                for j in 0..1000 {
+                    let size = 1 << ((i + j) % 5);
+                    let idx = buddies.allocate(size, 1).unwrap();
+                    buddies.deallocate(idx, size);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    #[cfg(feature = "quarantine")]
+    buddies.flush_quarantine();
+    assert!(buddies.is_unused());
+    assert!(buddies.verify_integrity().is_ok());
+}